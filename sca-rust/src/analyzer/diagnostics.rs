@@ -0,0 +1,89 @@
+use super::rules::{DEAD_CODE, USELESS_PREFIX, USELESS_SUFFIX};
+use super::source_map::{locate, offset_to_position, to_lsp_position};
+use super::tree::TreeAnalyzer;
+use crate::config::AppConfig;
+use crate::model::{FullNodeInfo, Severity};
+use anyhow::Result;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, TextEdit};
+
+/// Rules published to editors.
+const PUBLISHED_RULES: [&str; 3] = [DEAD_CODE, USELESS_PREFIX, USELESS_SUFFIX];
+
+/// A diagnostic together with the edit removing the entity, for dead code.
+#[derive(Debug, Clone)]
+pub struct EntityDiagnostic {
+    pub name: String,
+    pub diagnostic: Diagnostic,
+    pub removal: Option<TextEdit>,
+}
+
+/// Turns findings of an analysed document into diagnostics positioned in its raw source.
+pub struct DiagnosticBuilder {
+    tree_analyzer: TreeAnalyzer,
+}
+
+impl DiagnosticBuilder {
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        Ok(Self {
+            tree_analyzer: TreeAnalyzer::new(
+                config.grammar,
+                config.reference_nodes.clone(),
+                config.parameters.clone(),
+            )?,
+        })
+    }
+
+    pub fn build(&mut self, source: &str, nodes: &[FullNodeInfo]) -> Result<Vec<EntityDiagnostic>> {
+        let declarations = self.tree_analyzer.find_positions(source)?;
+        let mut located = Vec::new();
+        locate(nodes, &declarations, None, &mut located);
+
+        let mut diagnostics = Vec::new();
+        for entry in located {
+            let start = to_lsp_position(source, entry.declaration.start_position);
+            let range = Range {
+                start,
+                end: Position {
+                    line: start.line,
+                    character: start.character + entry.node.name.encode_utf16().count() as u32,
+                },
+            };
+            for finding in &entry.node.findings {
+                if !PUBLISHED_RULES.contains(&finding.rule.as_str()) {
+                    continue;
+                }
+                let removal = (finding.rule == DEAD_CODE).then(|| {
+                    let removal = &entry.declaration.removal;
+                    TextEdit {
+                        range: Range {
+                            start: offset_to_position(source, removal.start),
+                            end: offset_to_position(source, removal.end),
+                        },
+                        new_text: String::new(),
+                    }
+                });
+                diagnostics.push(EntityDiagnostic {
+                    name: entry.node.name.clone(),
+                    diagnostic: Diagnostic {
+                        range,
+                        severity: Some(to_lsp_severity(finding.severity)),
+                        code: Some(NumberOrString::String(finding.rule.clone())),
+                        source: Some("sca".to_string()),
+                        message: format!("{}: {}", entry.node.name, finding.message),
+                        ..Default::default()
+                    },
+                    removal,
+                });
+            }
+        }
+        Ok(diagnostics)
+    }
+}
+
+fn to_lsp_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Info => DiagnosticSeverity::INFORMATION,
+    }
+}
@@ -0,0 +1,117 @@
+use super::rules::{ProjectRule, DUPLICATE_NAME};
+use crate::config::DuplicateNameRule;
+use crate::model::FullNodeInfo;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Finds top-level entities sharing a name with entities of other files.
+pub struct DuplicateNames {
+    rule: DuplicateNameRule,
+    project_root: PathBuf,
+}
+
+impl DuplicateNames {
+    pub fn new(rule: DuplicateNameRule, project_root: &Path) -> Self {
+        Self {
+            rule,
+            project_root: project_root.to_path_buf(),
+        }
+    }
+
+    fn compared(&self, node: &FullNodeInfo) -> bool {
+        (!self.rule.exported_only || node.exported)
+            && !self.rule.allow.contains(&node.name)
+            && (self.rule.node_types.is_empty() || self.rule.node_types.contains(&node.node_type))
+    }
+
+    fn display(&self, path: &Path) -> String {
+        path.strip_prefix(&self.project_root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+impl ProjectRule for DuplicateNames {
+    fn id(&self) -> &'static str {
+        DUPLICATE_NAME
+    }
+
+    fn check(&self, nodes: &[FullNodeInfo]) -> Vec<(Vec<usize>, String)> {
+        let mut by_name: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, node) in nodes.iter().enumerate() {
+            if self.compared(node) {
+                by_name.entry(&node.name).or_default().push(index);
+            }
+        }
+
+        let mut findings = Vec::new();
+        for indexes in by_name.values() {
+            for &index in indexes {
+                let others: BTreeSet<String> = indexes
+                    .iter()
+                    .map(|&other| &nodes[other].file_path)
+                    .filter(|path| **path != nodes[index].file_path)
+                    .map(|path| self.display(path))
+                    .collect();
+                if !others.is_empty() {
+                    let others = others.into_iter().collect::<Vec<_>>().join(", ");
+                    findings.push((vec![index], format!("name also declared in {others}")));
+                }
+            }
+        }
+        findings.sort();
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter::Point;
+
+    fn node(name: &str, file: &str, exported: bool) -> FullNodeInfo {
+        FullNodeInfo {
+            node_type: "interface_declaration".to_string(),
+            name: name.to_string(),
+            start_position: Point { row: 0, column: 17 },
+            file_path: PathBuf::from("/repo").join(file),
+            exported,
+            references: 1,
+            reference_locations: vec![],
+            findings: vec![],
+            metrics: Default::default(),
+            range: Default::default(),
+            raw_range: Default::default(),
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn reports_names_shared_across_files() {
+        let rule = DuplicateNames::new(
+            DuplicateNameRule {
+                node_types: vec![],
+                allow: ["Options".to_string()].into_iter().collect(),
+                exported_only: true,
+            },
+            Path::new("/repo"),
+        );
+        let nodes = vec![
+            node("Props", "src/a.ts", true),
+            node("Props", "src/b.ts", true),
+            node("Props", "src/c.ts", false),
+            node("Options", "src/a.ts", true),
+            node("Options", "src/b.ts", true),
+            node("User", "src/a.ts", true),
+        ];
+
+        assert_eq!(
+            rule.check(&nodes),
+            vec![
+                (vec![0], "name also declared in src/b.ts".to_string()),
+                (vec![1], "name also declared in src/a.ts".to_string()),
+            ]
+        );
+    }
+}
@@ -5,6 +5,8 @@ use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use tracing::debug;
 
+/// Files to analyse, sorted: `only` restricted to the configured directory and patterns,
+/// or every matching file of `analyzeDirectory`.
 pub fn collect_files(config: &AppConfig, only: Option<&HashSet<PathBuf>>) -> Result<Vec<PathBuf>> {
     let mut files = match only {
         Some(paths) => collect_from_list(config, paths)?,
@@ -0,0 +1,377 @@
+use super::naming;
+use super::rules::{DEAD_CODE, USELESS_PREFIX};
+use super::source_map::{has_finding, locate, to_lsp_position, to_offset};
+use super::tree::TreeAnalyzer;
+use super::Analyzer;
+use crate::config::AppConfig;
+use crate::model::{FullNodeInfo, NodeInfo};
+use anyhow::{anyhow, bail, Context, Result};
+use lsp_types::{DocumentChanges, OneOf, TextEdit, WorkspaceEdit};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// New content of a file once its fixes are applied.
+#[derive(Debug, Clone)]
+pub struct FileFix {
+    pub path: PathBuf,
+    pub original: String,
+    pub fixed: String,
+    pub removed: usize,
+    pub renamed: usize,
+}
+
+/// A useless prefix [`FixPlan::rename_prefixes`] left in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRename {
+    pub path: PathBuf,
+    pub name: String,
+    /// The name without the prefix, or `None` when nothing would be left of it.
+    pub new_name: Option<String>,
+}
+
+impl fmt::Display for SkippedRename {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.new_name {
+            Some(new_name) => write!(
+                f,
+                "Refusing to rename {} to {} in {}: {} already exists",
+                self.name,
+                new_name,
+                self.path.display(),
+                new_name
+            ),
+            None => write!(
+                f,
+                "Skipping rename of {} in {}: nothing left without the prefix",
+                self.name,
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl FileFix {
+    /// Unified diff with paths relative to `root`, suitable for `git apply`; files
+    /// outside `root`, e.g. renamed in another package, cannot be part of it.
+    pub fn unified_diff(&self, root: &Path) -> Result<String> {
+        let Ok(relative) = self.path.strip_prefix(root) else {
+            bail!(
+                "{} is outside the project root {}, so no patch can include it",
+                self.path.display(),
+                root.display()
+            );
+        };
+        let relative = relative.display().to_string();
+        Ok(TextDiff::from_lines(&self.original, &self.fixed)
+            .unified_diff()
+            .header(&format!("a/{relative}"), &format!("b/{relative}"))
+            .to_string())
+    }
+
+    /// Replaces the file through a temporary sibling so readers never see a partial write.
+    /// The permissions of the original file are kept.
+    pub fn write(&self, config: &AppConfig) -> Result<()> {
+        let permissions = fs::metadata(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?
+            .permissions();
+        let (bytes, _, _) = config.encoding.encode(&self.fixed);
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temporary = self.path.with_file_name(format!(".{file_name}.sca-fix"));
+        fs::write(&temporary, &bytes)
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        fs::set_permissions(&temporary, permissions)
+            .with_context(|| format!("Failed to set permissions of {}", temporary.display()))?;
+        fs::rename(&temporary, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))
+    }
+}
+
+/// Concatenated diffs of `fixes`, applicable with `git apply` from `root`.
+pub fn render_patch(fixes: &[FileFix], root: &Path) -> Result<String> {
+    fixes.iter().map(|fix| fix.unified_diff(root)).collect()
+}
+
+type Edit = (Range<usize>, String);
+
+#[derive(Debug)]
+struct FileEdits {
+    original: String,
+    edits: Vec<Edit>,
+    removed: usize,
+    renamed: usize,
+}
+
+/// Edits collected from dead code removals and prefix renames, applied all at once.
+///
+/// Analysis runs on sanitized sources, so the raw files are parsed again and entities
+/// are matched by node type, name and position among their siblings.
+pub struct FixPlan<'c> {
+    config: &'c AppConfig,
+    tree_analyzer: TreeAnalyzer,
+    files: BTreeMap<PathBuf, FileEdits>,
+}
+
+impl<'c> FixPlan<'c> {
+    pub fn new(config: &'c AppConfig) -> Result<Self> {
+        let tree_analyzer = TreeAnalyzer::new(
+            config.grammar,
+            config.reference_nodes.clone(),
+            config.parameters.clone(),
+        )?;
+        Ok(Self {
+            config,
+            tree_analyzer,
+            files: BTreeMap::new(),
+        })
+    }
+
+    /// Removes every entity reported as dead code.
+    pub fn remove_dead_code(&mut self, nodes: &[FullNodeInfo]) -> Result<()> {
+        for file_nodes in nodes.chunk_by(|a, b| a.file_path == b.file_path) {
+            let path = &file_nodes[0].file_path;
+            let declarations = self.declarations(path)?;
+            let mut located = Vec::new();
+            locate(file_nodes, &declarations, None, &mut located);
+
+            let ranges: Vec<_> = located
+                .iter()
+                .filter(|entry| has_finding(entry.node, DEAD_CODE))
+                .map(|entry| entry.declaration.removal.clone())
+                .collect();
+            let file = self.file(path)?;
+            file.removed += ranges.len();
+            file.edits
+                .extend(ranges.into_iter().map(|range| (range, String::new())));
+        }
+        Ok(())
+    }
+
+    /// Renames entities with a useless prefix through the language server.
+    ///
+    /// Renames to a name another member of the parent already has are refused, and
+    /// returned with the ones that would leave nothing of the name.
+    pub async fn rename_prefixes(
+        &mut self,
+        analyzer: &mut Analyzer,
+        nodes: &[FullNodeInfo],
+    ) -> Result<Vec<SkippedRename>> {
+        let mut skipped = Vec::new();
+        for file_nodes in nodes.chunk_by(|a, b| a.file_path == b.file_path) {
+            let path = file_nodes[0].file_path.clone();
+            let declarations = self.declarations(&path)?;
+            let mut located = Vec::new();
+            locate(file_nodes, &declarations, None, &mut located);
+
+            for entry in located {
+                let Some(parent) = entry.parent_name else {
+                    continue;
+                };
+                if !has_finding(entry.node, USELESS_PREFIX) {
+                    continue;
+                }
+                let name = &entry.node.name;
+                let new_name =
+                    naming::strip_parent_prefix(&self.config.naming.prefix, parent, name);
+                let source = self.file(&path)?.original.clone();
+                let new_name = match new_name {
+                    Some(new_name)
+                        if !self
+                            .tree_analyzer
+                            .member_names(&source, entry.declaration.start_position)?
+                            .contains(&new_name) =>
+                    {
+                        new_name
+                    }
+                    new_name => {
+                        skipped.push(SkippedRename {
+                            path: path.clone(),
+                            name: name.clone(),
+                            new_name,
+                        });
+                        continue;
+                    }
+                };
+
+                let position = to_lsp_position(&source, entry.declaration.start_position);
+                let Some(edit) = analyzer.rename(&path, &source, position, &new_name).await? else {
+                    tracing::warn!("Language server returned no edits renaming {}", name);
+                    continue;
+                };
+                for (uri, edits) in text_edits(edit)? {
+                    let target = uri
+                        .to_file_path()
+                        .map_err(|_| anyhow!("Rename edit for non-file URI {uri}"))?;
+                    let file = self.file(&target)?;
+                    for edit in edits {
+                        let range = to_offset(&file.original, edit.range.start)
+                            ..to_offset(&file.original, edit.range.end);
+                        file.edits.push((range, edit.new_text));
+                    }
+                }
+                self.file(&path)?.renamed += 1;
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Applies the collected edits to the original contents of every touched file.
+    pub fn into_fixes(self) -> Vec<FileFix> {
+        self.files
+            .into_iter()
+            .filter(|(_, file)| !file.edits.is_empty())
+            .map(|(path, file)| {
+                let mut fixed = file.original.clone();
+                for (range, text) in merge(file.edits).into_iter().rev() {
+                    fixed.replace_range(range, &text);
+                }
+                FileFix {
+                    path,
+                    original: file.original,
+                    fixed,
+                    removed: file.removed,
+                    renamed: file.renamed,
+                }
+            })
+            .collect()
+    }
+
+    fn declarations(&mut self, path: &Path) -> Result<Vec<NodeInfo>> {
+        let source = self.file(path)?.original.clone();
+        self.tree_analyzer
+            .find_positions(&source)
+            .with_context(|| format!("Tree-sitter failed for {}", path.display()))
+    }
+
+    fn file(&mut self, path: &Path) -> Result<&mut FileEdits> {
+        if !self.files.contains_key(path) {
+            let original = self.config.read_source(path)?;
+            self.files.insert(
+                path.to_path_buf(),
+                FileEdits {
+                    original,
+                    edits: Vec::new(),
+                    removed: 0,
+                    renamed: 0,
+                },
+            );
+        }
+        Ok(self.files.get_mut(path).expect("file was just inserted"))
+    }
+}
+
+/// Sorts `edits` and drops those overlapping an earlier, wider one, so a removal wins
+/// over renames inside it and each byte is removed once.
+fn merge(mut edits: Vec<Edit>) -> Vec<Edit> {
+    edits.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+    let mut merged: Vec<Edit> = Vec::with_capacity(edits.len());
+    for (range, text) in edits {
+        match merged.last_mut() {
+            Some((last, _)) if range.start < last.end || range == *last => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push((range, text)),
+        }
+    }
+    merged
+}
+
+fn text_edits(edit: WorkspaceEdit) -> Result<Vec<(Url, Vec<TextEdit>)>> {
+    let Some(changes) = edit.document_changes else {
+        return Ok(edit.changes.unwrap_or_default().into_iter().collect());
+    };
+    let DocumentChanges::Edits(documents) = changes else {
+        bail!("Rename requires file operations, which are not supported");
+    };
+    Ok(documents
+        .into_iter()
+        .map(|document| {
+            let edits = document
+                .edits
+                .into_iter()
+                .map(|edit| match edit {
+                    OneOf::Left(edit) => edit,
+                    OneOf::Right(annotated) => annotated.text_edit,
+                })
+                .collect();
+            (document.text_document.uri, edits)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removal_wins_over_edits_inside_it() {
+        let edits = vec![
+            (4..8, "name".to_string()),
+            (0..10, String::new()),
+            (12..14, "id".to_string()),
+        ];
+        assert_eq!(
+            merge(edits),
+            vec![(0..10, String::new()), (12..14, "id".to_string())]
+        );
+    }
+
+    #[test]
+    fn patch_refuses_files_outside_the_root() {
+        let fix = |path: &str| FileFix {
+            path: PathBuf::from(path),
+            original: "a\n".to_string(),
+            fixed: "b\n".to_string(),
+            removed: 0,
+            renamed: 1,
+        };
+        let root = Path::new("/repo/app");
+
+        let patch = render_patch(&[fix("/repo/app/src/a.ts")], root).unwrap();
+        assert!(patch.starts_with("--- a/src/a.ts\n+++ b/src/a.ts\n"));
+        assert!(render_patch(&[fix("/repo/lib/b.ts")], root).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_keeps_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("sca.json");
+        let raw_config = serde_json::json!({
+            "lspExecutable": "unused",
+            "lspName": "typescript",
+            "grammar": "typescript",
+            "projectRootPath": ".",
+            "analyzeDirectory": ".",
+            "referenceNodes": []
+        });
+        fs::write(&config_path, raw_config.to_string()).unwrap();
+        let config = crate::config::load_config(&config_path, None).unwrap();
+
+        let path = dir.path().join("build.ts");
+        fs::write(&path, "#!/usr/bin/env ts-node\nconst unused = 1;\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+        let fix = FileFix {
+            path: path.clone(),
+            original: String::new(),
+            fixed: "#!/usr/bin/env ts-node\n".to_string(),
+            removed: 1,
+            renamed: 0,
+        };
+        fix.write(&config).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), fix.fixed);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o754);
+    }
+}
@@ -0,0 +1,651 @@
+use super::provider::ReferenceProvider;
+use super::source_map;
+use super::transport::BoxFuture;
+use crate::config::AppConfig;
+use crate::sanitize::sanitize_with_offsets;
+use anyhow::{anyhow, bail, Context, Result};
+use lsp_types::{Location, Position, Range};
+use prost::Message;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tree_sitter::Point;
+use url::Url;
+
+/// The subset of the SCIP protobuf schema needed to count references.
+mod scip {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Index {
+        #[prost(message, repeated, tag = "2")]
+        pub documents: Vec<Document>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Document {
+        #[prost(string, tag = "1")]
+        pub relative_path: String,
+        #[prost(message, repeated, tag = "2")]
+        pub occurrences: Vec<Occurrence>,
+        #[prost(int32, tag = "6")]
+        pub position_encoding: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Occurrence {
+        /// `[startLine, startCharacter, endCharacter]` or with an `endLine` before the last.
+        #[prost(int32, repeated, tag = "1")]
+        pub range: Vec<i32>,
+        #[prost(string, tag = "2")]
+        pub symbol: String,
+    }
+
+    pub const UTF8_POSITIONS: i32 = 1;
+    pub const UTF32_POSITIONS: i32 = 3;
+}
+
+/// Unit of the character offsets stored in an index document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PositionEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn column(self, prefix: &str) -> u32 {
+        let units = match self {
+            Self::Utf8 => prefix.len(),
+            Self::Utf16 => prefix.encode_utf16().count(),
+            Self::Utf32 => prefix.chars().count(),
+        };
+        units as u32
+    }
+
+    fn byte_column(self, line: &str, column: u32) -> usize {
+        let mut units = 0;
+        for (offset, character) in line.char_indices() {
+            if units >= column as usize {
+                return offset;
+            }
+            units += match self {
+                Self::Utf8 => character.len_utf8(),
+                Self::Utf16 => character.len_utf16(),
+                Self::Utf32 => 1,
+            };
+        }
+        line.len()
+    }
+}
+
+struct Occurrence {
+    range: Range,
+    symbol: String,
+}
+
+struct IndexDocument {
+    encoding: PositionEncoding,
+    occurrences: Vec<Occurrence>,
+}
+
+/// Occurrences of every symbol of a SCIP index or LSIF dump, by file.
+struct ReferenceIndex {
+    documents: HashMap<PathBuf, IndexDocument>,
+    symbols: HashMap<String, Vec<(PathBuf, Range)>>,
+}
+
+impl ReferenceIndex {
+    /// LSIF dumps are JSON, anything else is read as a SCIP protobuf.
+    fn load(path: &Path, project_root: &Path) -> Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Failed to read index {}", path.display()))?;
+        let is_json = bytes
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .is_some_and(|byte| matches!(byte, b'{' | b'['));
+        if is_json {
+            let text = String::from_utf8(bytes)
+                .with_context(|| format!("Invalid LSIF dump {}", path.display()))?;
+            Self::from_lsif(&text, project_root)
+                .with_context(|| format!("Invalid LSIF dump {}", path.display()))
+        } else {
+            let index = scip::Index::decode(bytes.as_slice())
+                .with_context(|| format!("Invalid SCIP index {}", path.display()))?;
+            Ok(Self::from_scip(index, project_root))
+        }
+    }
+
+    /// Relative paths are resolved against the project root rather than the indexer's.
+    fn from_scip(index: scip::Index, project_root: &Path) -> Self {
+        let mut reference_index = Self {
+            documents: HashMap::new(),
+            symbols: HashMap::new(),
+        };
+        for document in index.documents {
+            let path = project_root.join(&document.relative_path);
+            let encoding = match document.position_encoding {
+                scip::UTF8_POSITIONS => PositionEncoding::Utf8,
+                scip::UTF32_POSITIONS => PositionEncoding::Utf32,
+                // TypeScript indexers count UTF-16 units, like the LSP.
+                _ => PositionEncoding::Utf16,
+            };
+            let mut occurrences = Vec::new();
+            for occurrence in document.occurrences {
+                let range = match occurrence.range[..] {
+                    [line, start, end] => (line, start, line, end),
+                    [start_line, start, end_line, end] => (start_line, start, end_line, end),
+                    _ => continue,
+                };
+                if occurrence.symbol.is_empty() {
+                    continue;
+                }
+                // Local symbols are only unique inside their document.
+                let symbol = if occurrence.symbol.starts_with("local ") {
+                    format!("{} {}", document.relative_path, occurrence.symbol)
+                } else {
+                    occurrence.symbol
+                };
+                occurrences.push(Occurrence {
+                    range: Range::new(
+                        Position::new(range.0 as u32, range.1 as u32),
+                        Position::new(range.2 as u32, range.3 as u32),
+                    ),
+                    symbol,
+                });
+            }
+            reference_index.add_document(path, encoding, occurrences);
+        }
+        reference_index
+    }
+
+    /// Ranges get the reference result reached through their `next` chain as symbol.
+    fn from_lsif(text: &str, project_root: &Path) -> Result<Self> {
+        let elements: Vec<Value> = if text.trim_start().starts_with('[') {
+            serde_json::from_str(text)?
+        } else {
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<serde_json::Result<_>>()?
+        };
+
+        let id = |value: &Value| value.to_string();
+        let mut indexed_root = None;
+        let mut documents = HashMap::new();
+        let mut ranges = HashMap::new();
+        let mut range_documents = HashMap::new();
+        let mut next = HashMap::new();
+        let mut reference_results = HashMap::new();
+        let mut items: HashMap<String, Vec<String>> = HashMap::new();
+        for element in &elements {
+            let label = element["label"].as_str().unwrap_or_default();
+            match (element["type"].as_str(), label) {
+                (Some("vertex"), "metaData") => {
+                    indexed_root = element["projectRoot"].as_str().map(str::to_string);
+                }
+                (Some("vertex"), "document") => {
+                    let uri = element["uri"]
+                        .as_str()
+                        .ok_or_else(|| anyhow!("Document without uri: {element}"))?;
+                    documents.insert(id(&element["id"]), uri.to_string());
+                }
+                (Some("vertex"), "range") => {
+                    let range: Range = serde_json::from_value(element.clone())
+                        .with_context(|| format!("Invalid range {element}"))?;
+                    ranges.insert(id(&element["id"]), range);
+                }
+                (Some("edge"), "contains") => {
+                    for range in element["inVs"].as_array().into_iter().flatten() {
+                        range_documents.insert(id(range), id(&element["outV"]));
+                    }
+                }
+                (Some("edge"), "next") => {
+                    next.insert(id(&element["outV"]), id(&element["inV"]));
+                }
+                (Some("edge"), "textDocument/references") => {
+                    reference_results.insert(id(&element["outV"]), id(&element["inV"]));
+                }
+                (Some("edge"), "item") => {
+                    let usages = element["inVs"].as_array().into_iter().flatten();
+                    items
+                        .entry(id(&element["outV"]))
+                        .or_default()
+                        .extend(usages.map(id));
+                    if let Some(document) = element.get("document") {
+                        for range in element["inVs"].as_array().into_iter().flatten() {
+                            range_documents
+                                .entry(id(range))
+                                .or_insert_with(|| id(document));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Dumps store absolute URIs of the machine that produced them.
+        let indexed_root = indexed_root
+            .and_then(|root| Url::parse(&root).ok())
+            .and_then(|root| root.to_file_path().ok());
+        let rebase = |uri: &str| -> Option<PathBuf> {
+            let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+            match indexed_root
+                .as_deref()
+                .and_then(|root| path.strip_prefix(root).ok())
+            {
+                Some(relative) => Some(project_root.join(relative)),
+                None => Some(path),
+            }
+        };
+        let located = |range_id: &String| -> Option<(PathBuf, Range)> {
+            let document = documents.get(range_documents.get(range_id)?)?;
+            Some((rebase(document)?, *ranges.get(range_id)?))
+        };
+
+        let mut occurrences: HashMap<PathBuf, Vec<Occurrence>> = HashMap::new();
+        for range_id in ranges.keys() {
+            let mut current = range_id.clone();
+            let mut result = None;
+            for _ in 0..elements.len() {
+                if let Some(found) = reference_results.get(&current) {
+                    result = Some(found.clone());
+                    break;
+                }
+                match next.get(&current) {
+                    Some(following) => current = following.clone(),
+                    None => break,
+                }
+            }
+            let (Some(result), Some((path, range))) = (result, located(range_id)) else {
+                continue;
+            };
+            occurrences.entry(path).or_default().push(Occurrence {
+                range,
+                symbol: format!("lsif {result}"),
+            });
+        }
+
+        let mut reference_index = Self {
+            documents: HashMap::new(),
+            symbols: HashMap::new(),
+        };
+        for (path, mut occurrences) in occurrences {
+            occurrences.sort_by_key(|occurrence| {
+                (
+                    occurrence.range.start.line,
+                    occurrence.range.start.character,
+                )
+            });
+            reference_index.documents.insert(
+                path,
+                IndexDocument {
+                    encoding: PositionEncoding::Utf16,
+                    occurrences,
+                },
+            );
+        }
+        // Reference results list the usages themselves, declarations included.
+        for (result, range_ids) in items {
+            let locations = range_ids.iter().filter_map(located).collect();
+            reference_index
+                .symbols
+                .insert(format!("lsif {result}"), locations);
+        }
+        Ok(reference_index)
+    }
+
+    fn add_document(
+        &mut self,
+        path: PathBuf,
+        encoding: PositionEncoding,
+        occurrences: Vec<Occurrence>,
+    ) {
+        for occurrence in &occurrences {
+            self.symbols
+                .entry(occurrence.symbol.clone())
+                .or_default()
+                .push((path.clone(), occurrence.range));
+        }
+        self.documents.insert(
+            path,
+            IndexDocument {
+                encoding,
+                occurrences,
+            },
+        );
+    }
+}
+
+/// A file being analysed: its sanitized text and the file on disk the index describes.
+struct OpenDocument {
+    path: PathBuf,
+    raw: String,
+    sanitized: String,
+    offsets: Vec<usize>,
+}
+
+/// References read from a SCIP index or LSIF dump instead of a language server.
+///
+/// Indexes describe the files on disk while entities are found in sanitized sources, so
+/// positions are mapped between both, like a language server given the sanitized text.
+pub struct IndexReferences {
+    config: AppConfig,
+    index_path: PathBuf,
+    index: ReferenceIndex,
+    open: HashMap<Url, OpenDocument>,
+}
+
+impl IndexReferences {
+    pub fn load(config: &AppConfig, index_path: &Path) -> Result<Self> {
+        let index = ReferenceIndex::load(index_path, &config.project_root_path)?;
+        tracing::info!(
+            "Loaded {} documents from index {}",
+            index.documents.len(),
+            index_path.display()
+        );
+        Ok(Self {
+            config: config.clone(),
+            index_path: index_path.to_path_buf(),
+            index,
+            open: HashMap::new(),
+        })
+    }
+
+    fn lookup(&self, uri: &Url, position: Point, name: &str) -> Result<Vec<Location>> {
+        let open = self
+            .open
+            .get(uri)
+            .ok_or_else(|| anyhow!("{uri} is not open"))?;
+        let Some(document) = self.index.documents.get(&open.path) else {
+            bail!(
+                "{} is missing from the index {}",
+                open.path.display(),
+                self.index_path.display()
+            );
+        };
+
+        let sanitized_offset = source_map::to_offset(
+            &open.sanitized,
+            Position::new(position.row as u32, position.column as u32),
+        );
+        let raw_offset = open.offsets[sanitized_offset];
+        let target = to_index_position(document.encoding, &open.raw, raw_offset);
+        let occurrence = document
+            .occurrences
+            .iter()
+            .find(|occurrence| contains(&occurrence.range, target))
+            .or_else(|| {
+                // Indexers disagreeing on columns still point at the name on that line.
+                document.occurrences.iter().find(|occurrence| {
+                    occurrence.range.start.line == target.line
+                        && index_text(document.encoding, &open.raw, &occurrence.range) == name
+                })
+            });
+        let Some(occurrence) = occurrence else {
+            tracing::warn!(
+                "No symbol for {name} at {}:{}:{} in the index",
+                open.path.display(),
+                target.line + 1,
+                target.character + 1
+            );
+            return Ok(Vec::new());
+        };
+
+        let mut locations = Vec::new();
+        let usages = self.index.symbols.get(&occurrence.symbol);
+        for (path, range) in usages.into_iter().flatten() {
+            let range = if *path == open.path {
+                // Usages removed by sanitizing are not seen by language servers either.
+                match to_sanitized_range(open, document.encoding, range) {
+                    Some(range) => range,
+                    None => continue,
+                }
+            } else {
+                *range
+            };
+            let uri = Url::from_file_path(path)
+                .map_err(|_| anyhow!("Unable to convert path {} to URL", path.display()))?;
+            locations.push(Location { uri, range });
+        }
+        Ok(locations)
+    }
+}
+
+impl ReferenceProvider for IndexReferences {
+    fn references<'a>(
+        &'a mut self,
+        uri: &'a Url,
+        position: Point,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Location>>> {
+        let locations = self.lookup(uri, position, name);
+        Box::pin(async move { locations })
+    }
+
+    fn open<'a>(&'a mut self, uri: &'a Url, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        let opened = uri
+            .to_file_path()
+            .map_err(|_| anyhow!("Unable to convert {uri} to a path"))
+            .and_then(|path| {
+                let raw = self.config.read_source(&path)?;
+                let (sanitized, offsets) = sanitize_with_offsets(&raw);
+                if sanitized != text {
+                    tracing::warn!(
+                        "{} differs from the file on disk, index positions may be off",
+                        path.display()
+                    );
+                }
+                self.open.insert(
+                    uri.clone(),
+                    OpenDocument {
+                        path,
+                        raw,
+                        sanitized,
+                        offsets,
+                    },
+                );
+                Ok(())
+            });
+        Box::pin(async move { opened })
+    }
+
+    fn close<'a>(&'a mut self, uri: &'a Url) -> BoxFuture<'a, Result<()>> {
+        self.open.remove(uri);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    let position = (position.line, position.character);
+    (range.start.line, range.start.character) <= position
+        && position < (range.end.line, range.end.character)
+}
+
+fn to_index_position(encoding: PositionEncoding, raw: &str, offset: usize) -> Position {
+    let before = &raw[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        encoding.column(&before[line_start..]),
+    )
+}
+
+fn from_index_position(encoding: PositionEncoding, raw: &str, position: Position) -> usize {
+    let line_start: usize = raw
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = raw[line_start..].lines().next().unwrap_or_default();
+    line_start + encoding.byte_column(line, position.character)
+}
+
+fn index_text<'a>(encoding: PositionEncoding, raw: &'a str, range: &Range) -> &'a str {
+    let start = from_index_position(encoding, raw, range.start);
+    let end = from_index_position(encoding, raw, range.end);
+    raw.get(start..end).unwrap_or_default()
+}
+
+fn to_sanitized_range(
+    open: &OpenDocument,
+    encoding: PositionEncoding,
+    range: &Range,
+) -> Option<Range> {
+    let to_sanitized = |position: Position| {
+        let raw_offset = from_index_position(encoding, &open.raw, position);
+        let offset = open.offsets.binary_search(&raw_offset).ok()?;
+        Some(source_map::offset_to_position(&open.sanitized, offset))
+    };
+    Some(Range::new(
+        to_sanitized(range.start)?,
+        to_sanitized(range.end)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_config;
+    use serde_json::json;
+
+    /// `User` is declared below a block comment, so sanitizing moves it up a line.
+    fn project(index_name: &str) -> (tempfile::TempDir, AppConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/models.ts"),
+            "/* User\n   model */\nexport interface User {\n  name: string;\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/app.ts"),
+            "import { User } from './models';\nconst user: User = { name: 'a' };\n",
+        )
+        .unwrap();
+        let raw_config = json!({
+            "referenceProvider": { "index": index_name },
+            "lspName": "typescript",
+            "grammar": "typescript",
+            "projectRootPath": ".",
+            "analyzeDirectory": "src",
+            "referenceNodes": []
+        });
+        fs::write(root.join("sca.json"), raw_config.to_string()).unwrap();
+        let config = load_config(&root.join("sca.json"), None).unwrap();
+        (dir, config)
+    }
+
+    async fn user_references(config: &AppConfig, index_path: &Path) -> Vec<(String, Range)> {
+        let models = config.project_root_path.join("src/models.ts");
+        let uri = Url::from_file_path(&models).unwrap();
+        let sanitized = crate::sanitize::sanitize_source(&fs::read_to_string(&models).unwrap());
+
+        let mut references = IndexReferences::load(config, index_path).unwrap();
+        references.open(&uri, &sanitized).await.unwrap();
+        let locations = references
+            .references(&uri, Point { row: 1, column: 17 }, "User")
+            .await
+            .unwrap();
+        references.close(&uri).await.unwrap();
+
+        let mut locations: Vec<_> = locations
+            .into_iter()
+            .map(|location| {
+                let path = location.uri.to_file_path().unwrap();
+                let relative = path.strip_prefix(&config.project_root_path).unwrap();
+                (relative.display().to_string(), location.range)
+            })
+            .collect();
+        locations.sort_by_key(|(path, range)| (path.clone(), range.start.line));
+        locations
+    }
+
+    fn expected_user_references() -> Vec<(String, Range)> {
+        let range =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+        vec![
+            ("src/app.ts".to_string(), range(0, 9, 13)),
+            ("src/app.ts".to_string(), range(1, 12, 16)),
+            // In the sanitized text, like a language server given it with didOpen.
+            ("src/models.ts".to_string(), range(1, 17, 21)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn reads_references_from_scip_indexes() {
+        let (_dir, config) = project("index.scip");
+        let occurrence = |range: Vec<i32>| scip::Occurrence {
+            range,
+            symbol: "scip-typescript npm app 1.0.0 src/`models.ts`/User#".to_string(),
+        };
+        let index = scip::Index {
+            documents: vec![
+                scip::Document {
+                    relative_path: "src/models.ts".to_string(),
+                    occurrences: vec![occurrence(vec![2, 17, 21])],
+                    position_encoding: 0,
+                },
+                scip::Document {
+                    relative_path: "src/app.ts".to_string(),
+                    occurrences: vec![occurrence(vec![0, 9, 13]), occurrence(vec![1, 12, 16])],
+                    position_encoding: 0,
+                },
+            ],
+        };
+        let index_path = config.project_root_path.join("index.scip");
+        fs::write(&index_path, index.encode_to_vec()).unwrap();
+
+        assert_eq!(
+            user_references(&config, &index_path).await,
+            expected_user_references()
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_references_from_lsif_dumps() {
+        let (_dir, config) = project("dump.lsif");
+        let range = |id, line, start, end| {
+            json!({
+                "id": id, "type": "vertex", "label": "range",
+                "start": { "line": line, "character": start },
+                "end": { "line": line, "character": end }
+            })
+        };
+        let edge = |id, label, out: u32, target: Value| {
+            let mut edge = json!({ "id": id, "type": "edge", "label": label, "outV": out });
+            let key = if target.is_array() { "inVs" } else { "inV" };
+            edge[key] = target;
+            edge
+        };
+        let mut item = edge(15, "item", 8, json!([5, 6]));
+        item["document"] = json!(3);
+        // Indexed in CI, under another checkout directory.
+        let elements = [
+            json!({ "id": 1, "type": "vertex", "label": "metaData", "projectRoot": "file:///ci/app" }),
+            json!({ "id": 2, "type": "vertex", "label": "document", "uri": "file:///ci/app/src/models.ts" }),
+            json!({ "id": 3, "type": "vertex", "label": "document", "uri": "file:///ci/app/src/app.ts" }),
+            range(4, 2, 17, 21),
+            range(5, 0, 9, 13),
+            range(6, 1, 12, 16),
+            json!({ "id": 7, "type": "vertex", "label": "resultSet" }),
+            json!({ "id": 8, "type": "vertex", "label": "referenceResult" }),
+            edge(9, "contains", 2, json!([4])),
+            edge(10, "contains", 3, json!([5, 6])),
+            edge(11, "next", 4, json!(7)),
+            edge(12, "next", 5, json!(7)),
+            edge(13, "next", 6, json!(7)),
+            edge(14, "textDocument/references", 7, json!(8)),
+            item,
+            edge(16, "item", 8, json!([4])),
+        ];
+        let dump: Vec<_> = elements.iter().map(Value::to_string).collect();
+        let index_path = config.project_root_path.join("dump.lsif");
+        fs::write(&index_path, dump.join("\n")).unwrap();
+
+        assert_eq!(
+            user_references(&config, &index_path).await,
+            expected_user_references()
+        );
+    }
+}
@@ -1,58 +1,45 @@
-use crate::config::AppConfig;
+use super::session::{RecordingTransport, SessionRecorder, SessionReplay};
+use super::transport::{self, Transport};
+use crate::config::{AppConfig, LspSession, LspTransport};
 use anyhow::{anyhow, bail, Context, Result};
 use lsp_types::{
     ClientCapabilities, ClientInfo, ConfigurationParams, DidCloseTextDocumentParams,
-    DidOpenTextDocumentParams, InitializeParams, Location, ReferenceContext, ReferenceParams,
-    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Url,
-    WorkDoneProgressParams, WorkspaceFolder,
+    DidOpenTextDocumentParams, InitializeParams, InitializeResult, Location, OneOf, Position,
+    ReferenceContext, ReferenceParams, RenameParams, ServerCapabilities, TextDocumentIdentifier,
+    TextDocumentItem, TextDocumentPositionParams, Url, WorkDoneProgressParams, WorkspaceEdit,
+    WorkspaceFolder,
 };
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::process::Stdio;
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
-use tokio::task::JoinHandle;
-use tree_sitter::Point;
 
 const JSONRPC_VERSION: &str = "2.0";
 
 pub struct LspClient {
-    child: Child,
-    stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
-    stderr_task: Option<JoinHandle<()>>,
+    transport: Box<dyn Transport>,
+    shared_server: bool,
     request_id: u64,
     pending_requests: HashMap<u64, String>,
     workspace_folders: Vec<WorkspaceFolder>,
+    capabilities: ServerCapabilities,
 }
 
 impl LspClient {
     pub async fn new(config: &AppConfig) -> Result<Self> {
-        let mut command = Command::new(&config.lsp_executable);
-        command.args(&config.lsp_args);
-        command.stdin(Stdio::piped());
-        command.stdout(Stdio::piped());
-        command.stderr(Stdio::piped());
-        command.current_dir(&config.project_root_path);
-
-        let mut child = command
-            .spawn()
-            .with_context(|| format!("Failed to spawn {}", config.lsp_executable))?;
-
-        let stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("LSP process stdin not available"))?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("LSP process stdout not available"))?;
-
-        let stderr = child.stderr.take();
-        let stderr_task = stderr.map(spawn_stderr_logger);
+        let transport: Box<dyn Transport> = match &config.lsp_session {
+            LspSession::Live => transport::connect(config).await?,
+            LspSession::Record(path) => Box::new(RecordingTransport::new(
+                transport::connect(config).await?,
+                SessionRecorder::create(path).await?,
+            )),
+            LspSession::Replay(path) => Box::new(SessionReplay::load(path)?),
+        };
+        Self::with_transport(config, transport).await
+    }
 
+    /// Initializes a client over an already established transport.
+    pub async fn with_transport(config: &AppConfig, transport: Box<dyn Transport>) -> Result<Self> {
         let workspace_uri = Url::from_directory_path(&config.project_root_path).map_err(|_| {
             anyhow!(
                 "Failed to convert project root {} to URI",
@@ -65,13 +52,13 @@ impl LspClient {
         }];
 
         let mut client = Self {
-            child,
-            stdin,
-            stdout: BufReader::new(stdout),
-            stderr_task,
+            transport,
+            // A server reached over a socket outlives this run and may serve other clients.
+            shared_server: config.lsp_transport != LspTransport::Stdio,
             request_id: 1,
             pending_requests: HashMap::new(),
             workspace_folders,
+            capabilities: ServerCapabilities::default(),
         };
 
         client.initialize(config).await?;
@@ -82,7 +69,7 @@ impl LspClient {
         let capabilities = config
             .lsp_capabilities
             .clone()
-            .map(|value| serde_json::from_value::<ClientCapabilities>(value))
+            .map(serde_json::from_value::<ClientCapabilities>)
             .transpose()
             .context("Failed to parse lspCapabilities")?
             .unwrap_or_default();
@@ -105,7 +92,16 @@ impl LspClient {
         let response = self
             .send_request("initialize", serde_json::to_value(params)?)
             .await?;
-        let _: Value = response;
+        let result: InitializeResult =
+            serde_json::from_value(response).context("Invalid initialize response")?;
+        let server_name = result
+            .server_info
+            .map(|info| info.name)
+            .unwrap_or_else(|| config.lsp_name.clone());
+        if !supports_references(&result.capabilities) {
+            bail!("Language server {server_name} does not provide textDocument/references");
+        }
+        self.capabilities = result.capabilities;
 
         self.send_notification("initialized", Value::Null).await?;
 
@@ -120,6 +116,11 @@ impl LspClient {
         Ok(())
     }
 
+    /// Capabilities announced by the server in its `initialize` response.
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+
     pub async fn did_open(
         &mut self,
         uri: &Url,
@@ -148,17 +149,18 @@ impl LspClient {
             .await
     }
 
-    pub async fn references(&mut self, uri: &Url, position: Point) -> Result<usize> {
+    pub async fn references(
+        &mut self,
+        uri: &Url,
+        position: lsp_types::Position,
+    ) -> Result<Vec<Location>> {
         const MAX_RETRIES: u32 = 3;
         const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
         let params = ReferenceParams {
             text_document_position: TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier { uri: uri.clone() },
-                position: lsp_types::Position {
-                    line: position.row as u32,
-                    character: position.column as u32,
-                },
+                position,
             },
             context: ReferenceContext {
                 include_declaration: true,
@@ -171,7 +173,7 @@ impl LspClient {
 
         for attempt in 1..=MAX_RETRIES {
             let params_value = serde_json::to_value(&params)?;
-            
+
             match tokio::time::timeout(
                 REQUEST_TIMEOUT,
                 self.send_request("textDocument/references", params_value),
@@ -181,7 +183,7 @@ impl LspClient {
                 Ok(Ok(response)) => {
                     let locations: Option<Vec<Location>> =
                         serde_json::from_value(response).context("Invalid references response")?;
-                    return Ok(locations.map(|v| v.len()).unwrap_or(0));
+                    return Ok(locations.unwrap_or_default());
                 }
                 Ok(Err(e)) => {
                     tracing::warn!(
@@ -213,17 +215,37 @@ impl LspClient {
         Err(last_error.unwrap_or_else(|| anyhow!("All retry attempts failed")))
     }
 
+    /// Requests the edits renaming the symbol at `position`; `None` when nothing can be renamed.
+    pub async fn rename(
+        &mut self,
+        uri: &Url,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        if self.capabilities.rename_provider.is_none() {
+            bail!("Language server does not provide textDocument/rename");
+        }
+        let params = RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position,
+            },
+            new_name: new_name.to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+        let response = self
+            .send_request("textDocument/rename", serde_json::to_value(params)?)
+            .await?;
+        serde_json::from_value(response).context("Invalid rename response")
+    }
+
     pub async fn shutdown(&mut self) -> Result<()> {
+        if self.shared_server {
+            return Ok(());
+        }
         let _ = self.send_request("shutdown", Value::Null).await?;
         self.send_notification("exit", Value::Null).await?;
-        if let Some(handle) = self.stderr_task.take() {
-            handle.abort();
-        }
-        let status = self.child.wait().await?;
-        if !status.success() {
-            tracing::warn!("LSP process exited with {:?}", status);
-        }
-        Ok(())
+        self.transport.close().await
     }
 
     async fn send_request(&mut self, method: &str, params: Value) -> Result<Value> {
@@ -272,46 +294,15 @@ impl LspClient {
     }
 
     async fn write_message(&mut self, value: &Value) -> Result<()> {
-        let body = serde_json::to_vec(value)?;
-        let header = format!("Content-Length: {}\r\n\r\n", body.len());
-        self.stdin.write_all(header.as_bytes()).await?;
-        self.stdin.write_all(&body).await?;
-        self.stdin.flush().await?;
-        Ok(())
+        self.transport.send(value).await
     }
 
     async fn read_message(&mut self) -> Result<IncomingMessage> {
-        let mut content_length: Option<usize> = None;
-        let mut line = String::new();
-
-        loop {
-            line.clear();
-            let read = self.stdout.read_line(&mut line).await?;
-            if read == 0 {
-                bail!("LSP server closed the stream");
-            }
-            let trimmed = line.trim_end();
-            if trimmed.is_empty() {
-                break;
-            }
-            if let Some(value) = trimmed.strip_prefix("Content-Length:") {
-                let len = value
-                    .trim()
-                    .parse::<usize>()
-                    .context("Invalid Content-Length")?;
-                content_length = Some(len);
-            }
-        }
-
-        let length = content_length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
-        let mut buffer = vec![0u8; length];
-        self.stdout.read_exact(&mut buffer).await?;
-        let value: Value = serde_json::from_slice(&buffer).context("Invalid JSON payload")?;
-
+        let value = self.transport.receive().await?;
         parse_message(value)
     }
 
-    async fn handle_notification(&self, notif: NotificationMessage) -> Result<()> {
+    async fn handle_notification(&mut self, notif: NotificationMessage) -> Result<()> {
         let NotificationMessage { method, params, .. } = notif;
         match method.as_str() {
             "window/logMessage" => {
@@ -369,25 +360,6 @@ impl LspClient {
     }
 }
 
-impl Drop for LspClient {
-    fn drop(&mut self) {
-        if let Some(handle) = self.stderr_task.take() {
-            handle.abort();
-        }
-        let _ = self.child.start_kill();
-    }
-}
-
-fn spawn_stderr_logger(stderr: ChildStderr) -> JoinHandle<()> {
-    tokio::spawn(async move {
-        let reader = BufReader::new(stderr);
-        let mut lines = reader.lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            tracing::debug!("LSP stderr: {}", line);
-        }
-    })
-}
-
 #[derive(Debug)]
 enum IncomingMessage {
     Response(ResponseMessage),
@@ -434,6 +406,14 @@ struct NotificationMessage {
     params: Option<Value>,
 }
 
+fn supports_references(capabilities: &ServerCapabilities) -> bool {
+    match &capabilities.references_provider {
+        Some(OneOf::Left(enabled)) => *enabled,
+        Some(OneOf::Right(_)) => true,
+        None => false,
+    }
+}
+
 fn parse_message(value: Value) -> Result<IncomingMessage> {
     if value.get("method").is_some() {
         if value.get("id").is_some() {
@@ -461,3 +441,95 @@ fn id_from(id: &Value) -> Result<u64> {
         _ => bail!("Unsupported id type {:?}", id),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::transport::FramedTransport;
+    use crate::config::load_config;
+    use tokio::io::{DuplexStream, ReadHalf, WriteHalf};
+    use tokio::task::JoinHandle;
+
+    type DuplexTransport = FramedTransport<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>;
+
+    fn test_config(dir: &std::path::Path) -> AppConfig {
+        let config_path = dir.join("sca.json");
+        let raw_config = json!({
+            "lspExecutable": "unused",
+            "lspName": "typescript",
+            "grammar": "typescript",
+            "projectRootPath": ".",
+            "analyzeDirectory": ".",
+            "referenceNodes": []
+        });
+        std::fs::write(&config_path, raw_config.to_string()).unwrap();
+        load_config(&config_path, None).unwrap()
+    }
+
+    /// Starts an in-memory server announcing `capabilities` that finds two references.
+    fn start_server(capabilities: Value) -> (DuplexTransport, JoinHandle<()>) {
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let (client_read, client_write) = tokio::io::split(client_io);
+        let (server_read, server_write) = tokio::io::split(server_io);
+
+        let handle = tokio::spawn(async move {
+            let mut server = FramedTransport::new(server_read, server_write);
+            while let Ok(message) = server.receive().await {
+                let result = match message["method"].as_str() {
+                    Some("initialize") => json!({ "capabilities": capabilities }),
+                    Some("textDocument/references") => {
+                        let location = json!({
+                            "uri": "file:///a.ts",
+                            "range": {
+                                "start": { "line": 0, "character": 0 },
+                                "end": { "line": 0, "character": 3 }
+                            }
+                        });
+                        json!([location, location])
+                    }
+                    Some("exit") => break,
+                    _ => Value::Null,
+                };
+                if let Some(id) = message.get("id") {
+                    let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                    server.send(&response).await.unwrap();
+                }
+            }
+        });
+
+        (FramedTransport::new(client_read, client_write), handle)
+    }
+
+    #[tokio::test]
+    async fn talks_to_server_over_in_memory_duplex() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let (transport, server) = start_server(json!({ "referencesProvider": true }));
+
+        let mut client = LspClient::with_transport(&config, Box::new(transport))
+            .await
+            .unwrap();
+        let uri = Url::parse("file:///a.ts").unwrap();
+        let references = client
+            .references(&uri, lsp_types::Position::new(0, 0))
+            .await
+            .unwrap();
+        assert_eq!(references.len(), 2);
+
+        client.shutdown().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_server_without_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path());
+        let (transport, _server) = start_server(json!({ "hoverProvider": true }));
+
+        let err = LspClient::with_transport(&config, Box::new(transport))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("textDocument/references"));
+    }
+}
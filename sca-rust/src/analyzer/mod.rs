@@ -1,41 +1,163 @@
+//! Analysis of source files: syntax trees, references from the language server and rules.
+
+pub mod diagnostics;
+mod duplicates;
 pub mod files;
+pub mod fix;
+mod index;
 pub mod report;
+pub mod rules;
+pub mod suppressions;
 
 mod lsp;
+mod naming;
+pub mod provider;
+mod reachability;
+mod references;
+mod session;
+mod source_map;
+mod transport;
 mod tree;
 
 use crate::config::AppConfig;
-use crate::model::{FullNodeInfo, NodeInfo};
-use crate::sanitize::sanitize_source;
+use crate::model::{FullNodeInfo, NodeInfo, SourceRange};
+use crate::sanitize::sanitize_with_offsets;
 use anyhow::{anyhow, Context, Result};
+use lsp_types::{Position, WorkspaceEdit};
+use provider::ReferenceProvider;
+use references::{filter_references, to_reference_location};
+use rules::RuleSet;
+use serde::{Deserialize, Serialize};
+use source_map::RawPoints;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use suppressions::{Suppressions, UnusedSuppression};
 use tree::TreeAnalyzer;
 use url::Url;
 
+/// JSON-RPC message transports, as used by `sca lsp` to talk to its editor, and the
+/// future type of their methods.
+pub use transport::{BoxFuture, FramedTransport, Transport};
+
+/// Result of analysing a set of files, as printed by the CLI or sent by the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectAnalysis {
+    pub files: usize,
+    pub nodes: Vec<FullNodeInfo>,
+    pub unused_suppressions: Vec<UnusedSuppression>,
+}
+
+/// Progress reported by [`Analyzer::analyze_project`].
+#[derive(Debug, Clone, Copy)]
+pub enum AnalysisEvent<'a> {
+    /// File `index` (counted from 1) of `total` is about to be analysed.
+    Analyzing {
+        index: usize,
+        total: usize,
+        path: &'a Path,
+    },
+    /// Final entities of one file.
+    Analyzed(&'a [FullNodeInfo]),
+}
+
+/// Analyses files with tree-sitter and a reference provider, then applies the rules.
 pub struct Analyzer {
     config: AppConfig,
     tree_analyzer: TreeAnalyzer,
-    lsp_client: lsp::LspClient,
-    version: i32,
+    references: Box<dyn ReferenceProvider>,
+    rules: RuleSet,
+    suppressions: BTreeMap<PathBuf, Suppressions>,
 }
 
 impl Analyzer {
+    /// Starts the configured reference provider, by default the language server.
     pub async fn new(config: &AppConfig) -> Result<Self> {
-        let tree_analyzer = TreeAnalyzer::new(config.grammar, config.reference_nodes.clone())?;
-        let lsp_client = lsp::LspClient::new(config).await?;
+        let references = provider::from_config(config).await?;
+        Self::with_provider(config, references)
+    }
+
+    /// Analyses with `references` instead of the configured reference provider.
+    pub fn with_provider(
+        config: &AppConfig,
+        references: Box<dyn ReferenceProvider>,
+    ) -> Result<Self> {
+        let rules = RuleSet::from_config(config)?;
+        let tree_analyzer = TreeAnalyzer::new(
+            config.grammar,
+            config.reference_nodes.clone(),
+            config.parameters.clone(),
+        )?;
         Ok(Self {
             config: config.clone(),
             tree_analyzer,
-            lsp_client,
-            version: 1,
+            references,
+            rules,
+            suppressions: BTreeMap::new(),
         })
     }
 
+    /// Analyses `files` and runs the project-level rules over all of them.
+    ///
+    /// `on_event` is told before each file is analysed and receives the final entities of
+    /// each file: as soon as it is analysed when no project-level rule is enabled,
+    /// otherwise once every file is.
+    pub async fn analyze_project(
+        &mut self,
+        files: &[PathBuf],
+        mut on_event: impl FnMut(AnalysisEvent<'_>),
+    ) -> Result<ProjectAnalysis> {
+        let streaming = !self.rules.has_project_rules();
+        let mut nodes = Vec::new();
+        for (index, path) in files.iter().enumerate() {
+            on_event(AnalysisEvent::Analyzing {
+                index: index + 1,
+                total: files.len(),
+                path,
+            });
+            let file_nodes = self
+                .analyze_file(path)
+                .await
+                .with_context(|| format!("Failed to analyze {}", path.display()))?;
+            if streaming && !file_nodes.is_empty() {
+                on_event(AnalysisEvent::Analyzed(&file_nodes));
+            }
+            nodes.extend(file_nodes);
+        }
+
+        // Project-level rules need every file before their findings are known.
+        self.apply_project_rules(&mut nodes);
+        if !streaming {
+            for file_nodes in nodes.chunk_by(|a, b| a.file_path == b.file_path) {
+                on_event(AnalysisEvent::Analyzed(file_nodes));
+            }
+        }
+        // Suppressions of files analysed by earlier runs of a daemon are not reported.
+        let unused_suppressions = self
+            .unused_suppressions()
+            .into_iter()
+            .filter(|unused| files.contains(&unused.file_path))
+            .collect();
+        Ok(ProjectAnalysis {
+            files: files.len(),
+            nodes,
+            unused_suppressions,
+        })
+    }
+
+    /// Analyses a single file; project-level rules are left to [`Self::apply_project_rules`].
     pub async fn analyze_file(&mut self, path: &Path) -> Result<Vec<FullNodeInfo>> {
         let source = self.config.read_source(path)?;
-        let sanitized = sanitize_source(&source);
+        self.analyze_source(path, &source).await
+    }
+
+    /// Analyses `source` as the content of `path`, e.g. an unsaved editor buffer.
+    pub async fn analyze_source(&mut self, path: &Path, source: &str) -> Result<Vec<FullNodeInfo>> {
+        let mut suppressions = Suppressions::parse(source);
+        let (sanitized, offsets) = sanitize_with_offsets(source);
+        let raw_points = RawPoints::new(source, &sanitized, offsets);
         let nodes = self
             .tree_analyzer
             .find_positions(&sanitized)
@@ -44,33 +166,38 @@ impl Analyzer {
         let uri = Url::from_file_path(path)
             .map_err(|_| anyhow!("Unable to convert path {} to URL", path.display()))?;
 
-        self.lsp_client
-            .did_open(&uri, &self.config.lsp_name, sanitized.clone(), self.version)
-            .await?;
-        self.version += 1;
+        self.references.open(&uri, &sanitized).await?;
 
-        let full_nodes = self
-            .enrich_nodes(&uri, path.to_path_buf(), nodes, None)
+        let mut full_nodes = self
+            .enrich_nodes(&uri, &sanitized, path.to_path_buf(), &raw_points, nodes)
             .await?;
+        self.rules.apply(&mut full_nodes, None);
+        if suppressions.is_empty() {
+            self.suppressions.remove(path);
+        } else {
+            suppressions.apply(&mut full_nodes);
+            self.suppressions.insert(path.to_path_buf(), suppressions);
+        }
 
-        self.lsp_client.did_close(&uri).await?;
+        self.references.close(&uri).await?;
         Ok(full_nodes)
     }
 
     fn enrich_nodes<'a>(
         &'a mut self,
         uri: &'a Url,
+        source: &'a str,
         path: PathBuf,
+        raw_points: &'a RawPoints,
         nodes: Vec<NodeInfo>,
-        parent_name: Option<String>,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<FullNodeInfo>>> + Send + 'a>> {
         Box::pin(async move {
             let mut enriched = Vec::with_capacity(nodes.len());
 
             for node in nodes {
-                let references = self
-                    .lsp_client
-                    .references(uri, node.start_position)
+                let locations = self
+                    .references
+                    .references(uri, node.start_position, &node.name)
                     .await
                     .with_context(|| {
                         format!(
@@ -80,25 +207,29 @@ impl Analyzer {
                             node.start_position.column
                         )
                     })?;
-
+                let references =
+                    filter_references(&self.config.reference_filter, uri, source, &node, locations);
+                let reference_locations: Vec<_> =
+                    references.iter().map(to_reference_location).collect();
                 let children = self
-                    .enrich_nodes(uri, path.clone(), node.children, Some(node.name.clone()))
+                    .enrich_nodes(uri, source, path.clone(), raw_points, node.children)
                     .await?;
 
-                let parent_prefix = parent_name
-                    .as_ref()
-                    .map(|parent| node.name.to_lowercase().starts_with(&parent.to_lowercase()))
-                    .unwrap_or(false);
-
-                let references_count = references.saturating_sub(1);
-
                 enriched.push(FullNodeInfo {
                     node_type: node.node_type,
                     name: node.name,
                     start_position: node.start_position,
                     file_path: path.clone(),
-                    references: references_count,
-                    parent_name_prefix: parent_prefix,
+                    exported: node.exported,
+                    references: references.len(),
+                    reference_locations,
+                    findings: Vec::new(),
+                    metrics: node.metrics,
+                    range: node.range,
+                    raw_range: SourceRange {
+                        start: raw_points.to_raw(node.range.start),
+                        end: raw_points.to_raw(node.range.end),
+                    },
                     children,
                 });
             }
@@ -107,17 +238,55 @@ impl Analyzer {
         })
     }
 
+    /// Renames the symbol at `position` of `path`, whose current content is `source`.
+    ///
+    /// The raw source is opened so the returned edits match the file on disk rather than
+    /// the sanitized text used for analysis.
+    pub async fn rename(
+        &mut self,
+        path: &Path,
+        source: &str,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        let uri = Url::from_file_path(path)
+            .map_err(|_| anyhow!("Unable to convert path {} to URL", path.display()))?;
+        self.references.open(&uri, source).await?;
+        let edit = self.references.rename(&uri, position, new_name).await;
+        self.references.close(&uri).await?;
+        edit
+    }
+
+    /// Shuts the reference provider down, e.g. stops or disconnects from the language server.
     pub async fn shutdown(&mut self) -> Result<()> {
-        self.lsp_client.shutdown().await
+        self.references.shutdown().await
     }
-}
 
-pub fn count_dead_entities(nodes: &[FullNodeInfo]) -> usize {
-    nodes
-        .iter()
-        .map(|node| {
-            let current = if node.references == 0 { 1 } else { 0 };
-            current + count_dead_entities(&node.children)
-        })
-        .sum()
+    /// Rules enabled by the configuration.
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Runs the rules comparing files with each other once every file has been analysed.
+    pub fn apply_project_rules(&mut self, nodes: &mut [FullNodeInfo]) {
+        self.rules.apply_project(nodes);
+        for node in nodes {
+            if let Some(suppressions) = self.suppressions.get_mut(&node.file_path) {
+                suppressions.apply(std::slice::from_mut(node));
+            }
+        }
+    }
+
+    /// Drops what is remembered about a deleted or no longer analysed file.
+    pub fn forget_file(&mut self, path: &Path) {
+        self.suppressions.remove(path);
+    }
+
+    /// Directives that silenced nothing in the files analysed so far.
+    pub fn unused_suppressions(&self) -> Vec<UnusedSuppression> {
+        self.suppressions
+            .iter()
+            .flat_map(|(path, suppressions)| suppressions.unused(path))
+            .collect()
+    }
 }
@@ -0,0 +1,106 @@
+use crate::config::PrefixRule;
+
+const INTERFACE_NODE: &str = "interface_declaration";
+
+/// `UserUserName` inside `User`: the name repeats its parent at the start.
+pub fn has_parent_prefix(rule: &PrefixRule, parent: &str, name: &str) -> bool {
+    rule.enabled
+        && parent.chars().count() >= rule.min_length
+        && normalize(rule, name).starts_with(&normalize(rule, parent))
+}
+
+/// `userServiceUserDto` inside `UserDto`: the name repeats its parent at the end.
+pub fn has_parent_suffix(rule: &PrefixRule, parent: &str, name: &str) -> bool {
+    rule.enabled
+        && rule.detect_suffix
+        && parent.chars().count() >= rule.min_length
+        && name.len() > parent.len()
+        && normalize(rule, name).ends_with(&normalize(rule, parent))
+}
+
+/// `UserUserName` inside `User` becomes `UserName`, `userName` becomes `name`; `None`
+/// when nothing usable remains once the prefix is dropped.
+pub fn strip_parent_prefix(rule: &PrefixRule, parent: &str, name: &str) -> Option<String> {
+    if !has_parent_prefix(rule, parent, name) {
+        return None;
+    }
+    let rest: String = name.chars().skip(parent.chars().count()).collect();
+    let first = rest.chars().next()?;
+    if !(first.is_alphabetic() || first == '_' || first == '$') {
+        return None;
+    }
+    if name.starts_with(char::is_lowercase) {
+        Some(first.to_lowercase().chain(rest.chars().skip(1)).collect())
+    } else {
+        Some(rest)
+    }
+}
+
+/// `IUser`: interfaces named with a Hungarian `I` prefix.
+pub fn is_hungarian_interface(node_type: &str, name: &str) -> bool {
+    let mut chars = name.chars();
+    node_type == INTERFACE_NODE
+        && matches!(
+            (chars.next(), chars.next()),
+            (Some('I'), Some(second)) if second.is_ascii_uppercase()
+        )
+}
+
+fn normalize(rule: &PrefixRule, value: &str) -> String {
+    if rule.case_sensitive {
+        value.to_string()
+    } else {
+        value.to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix_rule() -> PrefixRule {
+        PrefixRule {
+            enabled: true,
+            case_sensitive: false,
+            min_length: 3,
+            detect_suffix: true,
+        }
+    }
+
+    #[test]
+    fn detects_prefixes_and_suffixes() {
+        let rule = prefix_rule();
+        assert!(has_parent_prefix(&rule, "User", "userName"));
+        assert!(!has_parent_prefix(&rule, "Id", "identifier"));
+        assert!(has_parent_suffix(&rule, "UserDto", "UserServiceUserDto"));
+        assert!(!has_parent_suffix(&rule, "UserDto", "UserDto"));
+
+        let strict = PrefixRule {
+            case_sensitive: true,
+            ..prefix_rule()
+        };
+        assert!(!has_parent_prefix(&strict, "User", "userName"));
+    }
+
+    #[test]
+    fn strips_parent_prefixes() {
+        let rule = prefix_rule();
+        assert_eq!(
+            strip_parent_prefix(&rule, "User", "UserUserName").as_deref(),
+            Some("UserName")
+        );
+        assert_eq!(
+            strip_parent_prefix(&rule, "User", "userName").as_deref(),
+            Some("name")
+        );
+        assert_eq!(strip_parent_prefix(&rule, "User", "user2"), None);
+        assert_eq!(strip_parent_prefix(&rule, "User", "User"), None);
+    }
+
+    #[test]
+    fn detects_hungarian_interfaces() {
+        assert!(is_hungarian_interface(INTERFACE_NODE, "IUser"));
+        assert!(!is_hungarian_interface(INTERFACE_NODE, "Item"));
+        assert!(!is_hungarian_interface("class_declaration", "IUser"));
+    }
+}
@@ -0,0 +1,128 @@
+use super::index::IndexReferences;
+use super::lsp::LspClient;
+use super::source_map::to_lsp_position;
+use super::transport::BoxFuture;
+use crate::config::{AppConfig, ReferenceBackend};
+use anyhow::{bail, Result};
+use lsp_types::{Location, Position, WorkspaceEdit};
+use std::collections::HashMap;
+use tree_sitter::Point;
+use url::Url;
+
+/// Finds the usages of the entities declared in analysed files.
+///
+/// The analyzer opens each document before looking up its entities and closes it
+/// afterwards, so backends working on live buffers see the text being analysed.
+pub trait ReferenceProvider: Send {
+    /// Locations referencing `name`, declared at `position` of `uri`, declaration included.
+    fn references<'a>(
+        &'a mut self,
+        uri: &'a Url,
+        position: Point,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Location>>>;
+
+    /// Makes `text` the content of `uri` until [`Self::close`]; ignored by default, for
+    /// backends that read the files on disk.
+    fn open<'a>(&'a mut self, _uri: &'a Url, _text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Forgets the content given to [`Self::open`]; does nothing by default.
+    fn close<'a>(&'a mut self, _uri: &'a Url) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Edits renaming the symbol at `position` of the open document `uri`.
+    fn rename<'a>(
+        &'a mut self,
+        _uri: &'a Url,
+        _position: Position,
+        _new_name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<WorkspaceEdit>>> {
+        Box::pin(async { bail!("The configured reference provider cannot rename symbols") })
+    }
+
+    /// Stops the backend once the analysis is done; does nothing by default.
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Starts the backend selected by `referenceProvider`.
+pub async fn from_config(config: &AppConfig) -> Result<Box<dyn ReferenceProvider>> {
+    match &config.reference_backend {
+        ReferenceBackend::Lsp => Ok(Box::new(LspReferences::new(config).await?)),
+        ReferenceBackend::Index(path) => Ok(Box::new(IndexReferences::load(config, path)?)),
+    }
+}
+
+/// References from `textDocument/references` of the configured language server.
+pub struct LspReferences {
+    client: LspClient,
+    language_id: String,
+    version: i32,
+    /// Open documents, to turn byte columns into the UTF-16 ones of the protocol.
+    documents: HashMap<Url, String>,
+}
+
+impl LspReferences {
+    pub async fn new(config: &AppConfig) -> Result<Self> {
+        let client = LspClient::new(config).await?;
+        let capabilities = client.capabilities();
+        tracing::debug!(
+            "LSP optional features: callHierarchy={}, implementation={}",
+            capabilities.call_hierarchy_provider.is_some(),
+            capabilities.implementation_provider.is_some()
+        );
+        Ok(Self {
+            client,
+            language_id: config.lsp_name.clone(),
+            version: 1,
+            documents: HashMap::new(),
+        })
+    }
+}
+
+impl ReferenceProvider for LspReferences {
+    fn references<'a>(
+        &'a mut self,
+        uri: &'a Url,
+        position: Point,
+        _name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Location>>> {
+        let position = match self.documents.get(uri) {
+            Some(text) => to_lsp_position(text, position),
+            None => Position::new(position.row as u32, position.column as u32),
+        };
+        Box::pin(self.client.references(uri, position))
+    }
+
+    fn open<'a>(&'a mut self, uri: &'a Url, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        let version = self.version;
+        self.version += 1;
+        self.documents.insert(uri.clone(), text.to_string());
+        Box::pin(
+            self.client
+                .did_open(uri, &self.language_id, text.to_string(), version),
+        )
+    }
+
+    fn close<'a>(&'a mut self, uri: &'a Url) -> BoxFuture<'a, Result<()>> {
+        self.documents.remove(uri);
+        Box::pin(self.client.did_close(uri))
+    }
+
+    fn rename<'a>(
+        &'a mut self,
+        uri: &'a Url,
+        position: Position,
+        new_name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<WorkspaceEdit>>> {
+        Box::pin(self.client.rename(uri, position, new_name))
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.client.shutdown())
+    }
+}
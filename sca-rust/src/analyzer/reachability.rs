@@ -0,0 +1,301 @@
+use super::rules::{ProjectRule, UNREACHABLE};
+use crate::config::{AppConfig, ReachabilityRule};
+use crate::model::{FullNodeInfo, ReferenceLocation};
+use globset::GlobSet;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Finds entities no entry point reaches through the reference graph, such as clusters
+/// of code only used by each other.
+///
+/// An entity is reached when it is used from a reached entity or from the top level of a
+/// reached module. Modules are reached when they are entry points or declare a reached
+/// entity, and members reach the entity declaring them. Every entity of an entry point
+/// is reached, so public API barrels keep what they export.
+///
+/// Usages from files that match the analysed directory and patterns but are missing from
+/// the analysed entities, e.g. left out by `--files-from`, cannot be followed further, so
+/// they count as reached rather than leaving what they use unreachable.
+pub struct Unreachable {
+    rule: ReachabilityRule,
+    project_root: PathBuf,
+    analyze_directory: PathBuf,
+    file_matching_glob: GlobSet,
+    file_exclude_glob: GlobSet,
+}
+
+struct Entity<'a> {
+    node: &'a FullNodeInfo,
+    /// Indexes from the top-level entity down to this one.
+    path: Vec<usize>,
+    parent: Option<usize>,
+}
+
+impl Unreachable {
+    pub fn new(rule: ReachabilityRule, config: &AppConfig) -> Self {
+        Self {
+            rule,
+            project_root: config.project_root_path.clone(),
+            analyze_directory: config.analyze_directory.clone(),
+            file_matching_glob: config.file_matching_glob.clone(),
+            file_exclude_glob: config.file_exclude_glob.clone(),
+        }
+    }
+
+    fn is_entry_point(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.project_root)
+            .is_ok_and(|relative| self.rule.entry_points.is_match(relative))
+    }
+
+    fn is_analysable(&self, path: &Path) -> bool {
+        path.starts_with(&self.analyze_directory)
+            && self.file_matching_glob.is_match(path)
+            && !self.file_exclude_glob.is_match(path)
+    }
+}
+
+impl ProjectRule for Unreachable {
+    fn id(&self) -> &'static str {
+        UNREACHABLE
+    }
+
+    fn check(&self, nodes: &[FullNodeInfo]) -> Vec<(Vec<usize>, String)> {
+        let mut entities = Vec::new();
+        flatten(nodes, &[], None, &mut entities);
+
+        // Graph vertices are the entities followed by one module per file.
+        let mut by_file: HashMap<&Path, Vec<usize>> = HashMap::new();
+        let mut modules: HashMap<&Path, usize> = HashMap::new();
+        for (index, entity) in entities.iter().enumerate() {
+            by_file
+                .entry(&entity.node.file_path)
+                .or_default()
+                .push(index);
+        }
+        let files = entities
+            .iter()
+            .map(|entity| entity.node.file_path.as_path())
+            .chain(entities.iter().flat_map(|entity| {
+                entity
+                    .node
+                    .reference_locations
+                    .iter()
+                    .map(|location| location.file_path.as_path())
+            }));
+        for file in files {
+            let next = entities.len() + modules.len();
+            modules.entry(file).or_insert(next);
+        }
+
+        let mut uses = vec![Vec::new(); entities.len() + modules.len()];
+        for (target, entity) in entities.iter().enumerate() {
+            for location in &entity.node.reference_locations {
+                let user = innermost(&entities, &by_file, entity.node, location)
+                    .unwrap_or(modules[location.file_path.as_path()]);
+                uses[user].push(target);
+            }
+        }
+
+        let mut reached = vec![false; uses.len()];
+        let mut pending: Vec<usize> = modules
+            .iter()
+            .filter(|(file, _)| self.is_entry_point(file))
+            .flat_map(|(file, &module)| {
+                let declared = by_file.get(file).into_iter().flatten().copied();
+                std::iter::once(module).chain(declared)
+            })
+            .collect();
+        if pending.is_empty() {
+            tracing::warn!("No analysed file or usage matches reachability.entryPoints");
+            return Vec::new();
+        }
+        pending.extend(
+            modules
+                .iter()
+                .filter(|(file, _)| !by_file.contains_key(*file) && self.is_analysable(file))
+                .map(|(_, &module)| module),
+        );
+        while let Some(vertex) = pending.pop() {
+            if std::mem::replace(&mut reached[vertex], true) {
+                continue;
+            }
+            pending.extend(&uses[vertex]);
+            if let Some(entity) = entities.get(vertex) {
+                pending.extend(entity.parent);
+                pending.push(modules[entity.node.file_path.as_path()]);
+            }
+        }
+
+        // Dead entities are already reported, and members go away with their parent.
+        entities
+            .iter()
+            .enumerate()
+            .filter(|(index, entity)| {
+                !reached[*index]
+                    && entity.node.references > 0
+                    && !entity.node.is_parameter()
+                    && entity.parent.is_none_or(|parent| reached[parent])
+            })
+            .map(|(_, entity)| {
+                (
+                    entity.path.clone(),
+                    "unreachable from entry points".to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn flatten<'a>(
+    nodes: &'a [FullNodeInfo],
+    path: &[usize],
+    parent: Option<usize>,
+    entities: &mut Vec<Entity<'a>>,
+) {
+    for (index, node) in nodes.iter().enumerate() {
+        let path = [path, &[index]].concat();
+        let current = entities.len();
+        entities.push(Entity {
+            node,
+            path: path.clone(),
+            parent,
+        });
+        flatten(&node.children, &path, Some(current), entities);
+    }
+}
+
+/// The innermost entity containing `location`, a usage of `target`.
+///
+/// Usages from the file declaring `target` are positioned in its sanitized source, usages
+/// from other files in the files on disk, like the language server reports them.
+fn innermost(
+    entities: &[Entity],
+    by_file: &HashMap<&Path, Vec<usize>>,
+    target: &FullNodeInfo,
+    location: &ReferenceLocation,
+) -> Option<usize> {
+    let same_file = location.file_path == target.file_path;
+    by_file
+        .get(location.file_path.as_path())?
+        .iter()
+        .copied()
+        .filter(|&index| {
+            let node = entities[index].node;
+            let range = if same_file {
+                node.range
+            } else {
+                node.raw_range
+            };
+            range.contains(location.start)
+        })
+        .max_by_key(|&index| {
+            let start = entities[index].node.range.start;
+            (start.row, start.column)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SourceRange;
+    use globset::{Glob, GlobSetBuilder};
+    use tree_sitter::Point;
+
+    fn point(row: usize) -> Point {
+        Point { row, column: 0 }
+    }
+
+    /// An entity spanning `rows` of `file`, used at the given rows of other files.
+    fn node(
+        name: &str,
+        file: &str,
+        rows: (usize, usize),
+        usages: &[(&str, usize)],
+    ) -> FullNodeInfo {
+        let range = SourceRange {
+            start: point(rows.0),
+            end: point(rows.1),
+        };
+        FullNodeInfo {
+            node_type: "class_declaration".to_string(),
+            name: name.to_string(),
+            start_position: range.start,
+            file_path: PathBuf::from("/repo").join(file),
+            exported: true,
+            references: usages.len(),
+            reference_locations: usages
+                .iter()
+                .map(|(file, row)| ReferenceLocation {
+                    file_path: PathBuf::from("/repo").join(file),
+                    start: point(*row),
+                    end: point(*row),
+                })
+                .collect(),
+            findings: vec![],
+            metrics: Default::default(),
+            range,
+            raw_range: range,
+            children: vec![],
+        }
+    }
+
+    fn glob(pattern: &str) -> GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        builder.add(Glob::new(pattern).unwrap());
+        builder.build().unwrap()
+    }
+
+    fn rule() -> Unreachable {
+        Unreachable {
+            rule: ReachabilityRule {
+                entry_points: glob("src/main.ts"),
+            },
+            project_root: PathBuf::from("/repo"),
+            analyze_directory: PathBuf::from("/repo/src"),
+            file_matching_glob: glob("**/*.ts"),
+            file_exclude_glob: GlobSet::empty(),
+        }
+    }
+
+    #[test]
+    fn reports_clusters_only_used_by_each_other() {
+        let rule = rule();
+
+        let mut service = node("Service", "src/service.ts", (0, 10), &[("src/main.ts", 1)]);
+        service.children = vec![
+            node("run", "src/service.ts", (2, 4), &[("src/main.ts", 2)]),
+            node("legacy", "src/service.ts", (5, 7), &[("src/old.ts", 3)]),
+        ];
+        let nodes = vec![
+            service,
+            // Uses `legacy` and is used by `Parser`, which it uses in turn.
+            node("Old", "src/old.ts", (0, 5), &[("src/parser.ts", 3)]),
+            node("Parser", "src/parser.ts", (0, 5), &[("src/old.ts", 2)]),
+            node("Unused", "src/unused.ts", (0, 1), &[]),
+        ];
+
+        assert_eq!(
+            rule.check(&nodes),
+            vec![
+                (vec![0, 1], "unreachable from entry points".to_string()),
+                (vec![1], "unreachable from entry points".to_string()),
+                (vec![2], "unreachable from entry points".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn usages_from_files_left_out_count_as_reached() {
+        // `src/old.ts` was not analysed, so what it uses may well be reached through it.
+        let nodes = vec![
+            node("App", "src/app.ts", (0, 5), &[("src/main.ts", 1)]),
+            node("Parser", "src/parser.ts", (0, 5), &[("src/old.ts", 2)]),
+            node("Script", "src/script.ts", (0, 5), &[("scripts/run.ts", 2)]),
+        ];
+
+        assert_eq!(
+            rule().check(&nodes),
+            vec![(vec![2], "unreachable from entry points".to_string())]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use super::source_map;
+use crate::config::ReferenceFilter;
+use crate::model::{NodeInfo, ReferenceLocation};
+use lsp_types::Location;
+use tree_sitter::Point;
+use url::Url;
+
+/// Returns the usages of `node` among `locations`: the declaration is removed and the
+/// configured filters are applied.
+///
+/// `source` is the text of `uri` the server was given, to compare its UTF-16 positions
+/// with the byte columns of `node`.
+pub fn filter_references(
+    filter: &ReferenceFilter,
+    uri: &Url,
+    source: &str,
+    node: &NodeInfo,
+    locations: Vec<Location>,
+) -> Vec<Location> {
+    let mut locations = locations;
+    let same_file = |location: &Location| {
+        (location.uri == *uri).then(|| source_map::to_point(source, location.range.start))
+    };
+
+    match locations
+        .iter()
+        .position(|location| same_file(location) == Some(node.start_position))
+    {
+        Some(declaration) => {
+            locations.remove(declaration);
+        }
+        // Servers that report the declaration elsewhere still include it once.
+        None if !locations.is_empty() => {
+            locations.remove(0);
+        }
+        None => {}
+    }
+
+    locations
+        .into_iter()
+        .filter(|location| {
+            !(filter.ignore_self_references
+                && same_file(location).is_some_and(|point| node.range.contains(point)))
+        })
+        .filter(|location| match location.uri.to_file_path() {
+            Ok(path) => !filter.exclude_glob.is_match(path),
+            Err(_) => true,
+        })
+        .collect()
+}
+
+pub fn to_reference_location(location: &Location) -> ReferenceLocation {
+    let file_path = location
+        .uri
+        .to_file_path()
+        .unwrap_or_else(|_| location.uri.path().into());
+    ReferenceLocation {
+        file_path,
+        start: to_point(location),
+        end: Point {
+            row: location.range.end.line as usize,
+            column: location.range.end.character as usize,
+        },
+    }
+}
+
+fn to_point(location: &Location) -> Point {
+    Point {
+        row: location.range.start.line as usize,
+        column: location.range.start.character as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SourceRange;
+    use globset::{Glob, GlobSet, GlobSetBuilder};
+    use lsp_types::{Position, Range};
+
+    fn location(uri: &Url, line: u32, character: u32) -> Location {
+        Location {
+            uri: uri.clone(),
+            range: Range {
+                start: Position { line, character },
+                end: Position {
+                    line,
+                    character: character + 3,
+                },
+            },
+        }
+    }
+
+    fn globs(patterns: &[&str]) -> GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern).unwrap());
+        }
+        builder.build().unwrap()
+    }
+
+    const SOURCE: &str = "import { tree } from './tree';\n\nfunction walk(node) {\n  if (node) {\n    walk(node.next);\n  }\n}\n";
+
+    fn node() -> NodeInfo {
+        NodeInfo {
+            node_type: "function_declaration".to_string(),
+            name: "walk".to_string(),
+            start_position: Point { row: 2, column: 9 },
+            range: SourceRange {
+                start: Point { row: 2, column: 0 },
+                end: Point { row: 6, column: 1 },
+            },
+            exported: false,
+            removal: 0..0,
+            metrics: Default::default(),
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn drops_self_references_and_excluded_files() {
+        let uri = Url::parse("file:///src/walk.ts").unwrap();
+        let spec = Url::parse("file:///src/walk.spec.ts").unwrap();
+        let other = Url::parse("file:///src/main.ts").unwrap();
+        let locations = vec![
+            location(&uri, 2, 9),
+            location(&uri, 4, 4),
+            location(&spec, 1, 0),
+            location(&other, 8, 2),
+        ];
+
+        let unfiltered = ReferenceFilter {
+            ignore_self_references: false,
+            exclude_glob: GlobSet::empty(),
+        };
+        assert_eq!(
+            filter_references(&unfiltered, &uri, SOURCE, &node(), locations.clone()).len(),
+            3
+        );
+
+        let filter = ReferenceFilter {
+            ignore_self_references: true,
+            exclude_glob: globs(&["**/*.spec.ts"]),
+        };
+        let kept = filter_references(&filter, &uri, SOURCE, &node(), locations);
+        assert_eq!(kept, vec![location(&other, 8, 2)]);
+    }
+
+    #[test]
+    fn recognises_declaration_after_non_ascii_text() {
+        let uri = Url::parse("file:///src/walk.ts").unwrap();
+        let other = Url::parse("file:///src/main.ts").unwrap();
+        // `ü` takes two bytes but one UTF-16 unit, so `walk` starts at byte 25, unit 24.
+        let source = "\n\nconst s = \"ü\"; function walk() {}\n";
+        let mut node = node();
+        node.start_position = Point { row: 2, column: 25 };
+        let locations = vec![location(&other, 8, 2), location(&uri, 2, 24)];
+
+        let filter = ReferenceFilter {
+            ignore_self_references: false,
+            exclude_glob: GlobSet::empty(),
+        };
+        assert_eq!(
+            filter_references(&filter, &uri, source, &node, locations),
+            vec![location(&other, 8, 2)]
+        );
+    }
+}
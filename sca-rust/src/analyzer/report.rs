@@ -1,5 +1,10 @@
-use crate::model::FullNodeInfo;
+use crate::model::{FullNodeInfo, Severity};
+use std::collections::BTreeSet;
 
+/// Entities with at most this many usages list the files they are used in.
+const USED_ONLY_IN_MAX_REFERENCES: usize = 2;
+
+/// Console report of one file's nodes; entities without findings only with `show_all`.
 pub fn build_report(nodes: &[FullNodeInfo], show_all: bool) -> String {
     if nodes.is_empty() {
         return String::new();
@@ -41,10 +46,14 @@ fn render_node(node: &FullNodeInfo, show_all: bool, depth: usize) -> Option<Stri
     let padding = "\t".repeat(depth);
     let reason_str = reasons.join(", ");
 
-    let mut lines = vec![format!(
+    let mut line = format!(
         "{padding}[{status}] {}:{}:{} :: ({reason_str})",
         node.name, node.start_position.row, node.start_position.column
-    )];
+    );
+    if let Some(files) = used_only_in(node) {
+        line.push_str(&format!(" used only in {files}"));
+    }
+    let mut lines = vec![line];
 
     for child in &node.children {
         if let Some(child_line) = render_node(child, show_all, depth + 1) {
@@ -55,19 +64,39 @@ fn render_node(node: &FullNodeInfo, show_all: bool, depth: usize) -> Option<Stri
     Some(lines.join("\n"))
 }
 
-fn reasons(node: &FullNodeInfo) -> Vec<String> {
-    let mut reasons = Vec::new();
-    if node.references == 0 {
-        reasons.push("dead code".to_string());
-    }
-    if node.parent_name_prefix {
-        reasons.push("useless prefix".to_string());
+fn used_only_in(node: &FullNodeInfo) -> Option<String> {
+    if node.reference_locations.is_empty()
+        || node.reference_locations.len() > USED_ONLY_IN_MAX_REFERENCES
+    {
+        return None;
     }
-    reasons
+    let files: BTreeSet<String> = node
+        .reference_locations
+        .iter()
+        .map(|location| {
+            location
+                .file_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| location.file_path.display().to_string())
+        })
+        .collect();
+    Some(files.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+/// Smells found on a single node, without looking at its children.
+pub fn reasons(node: &FullNodeInfo) -> Vec<String> {
+    node.findings
+        .iter()
+        .map(|finding| match finding.severity {
+            Severity::Error => finding.message.clone(),
+            severity => format!("{} [{}]", finding.message, severity.as_str()),
+        })
+        .collect()
 }
 
 fn has_errors(node: &FullNodeInfo) -> bool {
-    node.references == 0 || node.parent_name_prefix || node.children.iter().any(has_errors)
+    !reasons(node).is_empty() || node.children.iter().any(has_errors)
 }
 
 #[cfg(test)]
@@ -83,8 +112,17 @@ mod tests {
             name: "Foo".to_string(),
             start_position: Point { row: 10, column: 2 },
             file_path: "test".into(),
+            exported: false,
             references: 0,
-            parent_name_prefix: false,
+            reference_locations: vec![],
+            findings: vec![crate::model::Finding {
+                rule: "dead-code".to_string(),
+                severity: Severity::Error,
+                message: "dead code".to_string(),
+            }],
+            metrics: Default::default(),
+            range: Default::default(),
+            raw_range: Default::default(),
             children: vec![],
         };
 
@@ -92,4 +130,30 @@ mod tests {
         assert!(report.contains("💩"));
         assert!(report.contains("dead code"));
     }
+
+    #[test]
+    fn lists_files_of_rarely_used_entities() {
+        let location = |file: &str| crate::model::ReferenceLocation {
+            file_path: file.into(),
+            start: Point { row: 1, column: 0 },
+            end: Point { row: 1, column: 3 },
+        };
+        let node = FullNodeInfo {
+            node_type: "interface".to_string(),
+            name: "Foo".to_string(),
+            start_position: Point { row: 10, column: 2 },
+            file_path: "src/foo.ts".into(),
+            exported: false,
+            references: 2,
+            reference_locations: vec![location("src/b.ts"), location("src/a.ts")],
+            findings: vec![],
+            metrics: Default::default(),
+            range: Default::default(),
+            raw_range: Default::default(),
+            children: vec![],
+        };
+
+        let report = render_node(&node, true, 0).unwrap();
+        assert!(report.ends_with("used only in a.ts, b.ts"));
+    }
 }
@@ -0,0 +1,535 @@
+use super::duplicates::DuplicateNames;
+use super::naming;
+use super::reachability::Unreachable;
+use crate::config::{AppConfig, LowUsageRule, PrefixRule, RuleSettings, SizeLimits};
+use crate::model::{Finding, FullNodeInfo, NodeMetrics, Severity};
+use anyhow::{bail, Result};
+use globset::GlobSet;
+use regex::Regex;
+use std::collections::HashMap;
+
+pub const DEAD_CODE: &str = "dead-code";
+pub const USELESS_PREFIX: &str = "useless-prefix";
+pub const USELESS_SUFFIX: &str = "useless-suffix";
+pub const HUNGARIAN_INTERFACE: &str = "hungarian-interface";
+pub const NAMING_CONVENTION: &str = "naming-convention";
+pub const IDENTIFIER_LENGTH: &str = "identifier-length";
+pub const TEST_ONLY_USAGE: &str = "test-only-usage";
+pub const LOW_USAGE: &str = "low-usage";
+pub const TOO_MANY_PROPERTIES: &str = "too-many-properties";
+pub const TOO_MANY_METHODS: &str = "too-many-methods";
+pub const TOO_MANY_LINES: &str = "too-many-lines";
+pub const NESTING_DEPTH: &str = "nesting-depth";
+pub const DUPLICATE_NAME: &str = "duplicate-name";
+pub const UNUSED_PARAMETER: &str = "unused-parameter";
+pub const UNREACHABLE: &str = "unreachable";
+
+const RULE_IDS: [&str; 15] = [
+    DEAD_CODE,
+    USELESS_PREFIX,
+    USELESS_SUFFIX,
+    HUNGARIAN_INTERFACE,
+    NAMING_CONVENTION,
+    IDENTIFIER_LENGTH,
+    TEST_ONLY_USAGE,
+    LOW_USAGE,
+    TOO_MANY_PROPERTIES,
+    TOO_MANY_METHODS,
+    TOO_MANY_LINES,
+    NESTING_DEPTH,
+    DUPLICATE_NAME,
+    UNUSED_PARAMETER,
+    UNREACHABLE,
+];
+
+/// A single smell check applied to every analysed entity.
+pub trait Rule: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    /// Parameters are only checked by rules that opt in; entity rules such as dead code
+    /// would report every unused parameter twice.
+    fn checks_parameters(&self) -> bool {
+        false
+    }
+
+    /// Returns the finding message when `node` violates the rule.
+    fn check(&self, node: &FullNodeInfo, parent_name: Option<&str>) -> Option<String>;
+}
+
+/// A check comparing entities across files, run once every file has been analysed.
+pub trait ProjectRule: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    /// Returns finding messages keyed by the indexes leading from a top-level entity
+    /// through its children to the offending one.
+    fn check(&self, nodes: &[FullNodeInfo]) -> Vec<(Vec<usize>, String)>;
+}
+
+struct DeadCode;
+
+impl Rule for DeadCode {
+    fn id(&self) -> &'static str {
+        DEAD_CODE
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        (node.references == 0).then(|| "dead code".to_string())
+    }
+}
+
+struct UnusedParameter;
+
+impl Rule for UnusedParameter {
+    fn id(&self) -> &'static str {
+        UNUSED_PARAMETER
+    }
+
+    fn checks_parameters(&self) -> bool {
+        true
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        if !node.is_parameter() || node.references > 0 {
+            return None;
+        }
+        let message = match node.node_type.as_str() {
+            "required_parameter" | "optional_parameter" => "unused parameter",
+            _ => "unused destructured property",
+        };
+        Some(message.to_string())
+    }
+}
+
+struct UselessPrefix(PrefixRule);
+
+impl Rule for UselessPrefix {
+    fn id(&self) -> &'static str {
+        USELESS_PREFIX
+    }
+
+    fn check(&self, node: &FullNodeInfo, parent_name: Option<&str>) -> Option<String> {
+        let parent = parent_name?;
+        naming::has_parent_prefix(&self.0, parent, &node.name).then(|| "useless prefix".to_string())
+    }
+}
+
+struct UselessSuffix(PrefixRule);
+
+impl Rule for UselessSuffix {
+    fn id(&self) -> &'static str {
+        USELESS_SUFFIX
+    }
+
+    fn check(&self, node: &FullNodeInfo, parent_name: Option<&str>) -> Option<String> {
+        let parent = parent_name?;
+        naming::has_parent_suffix(&self.0, parent, &node.name).then(|| "useless suffix".to_string())
+    }
+}
+
+struct HungarianInterface;
+
+impl Rule for HungarianInterface {
+    fn id(&self) -> &'static str {
+        HUNGARIAN_INTERFACE
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        naming::is_hungarian_interface(&node.node_type, &node.name)
+            .then(|| "hungarian interface prefix".to_string())
+    }
+}
+
+struct NamingConvention(HashMap<String, Regex>);
+
+impl Rule for NamingConvention {
+    fn id(&self) -> &'static str {
+        NAMING_CONVENTION
+    }
+
+    fn checks_parameters(&self) -> bool {
+        true
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        let pattern = self.0.get(&node.node_type)?;
+        (!pattern.is_match(&node.name)).then(|| format!("name does not match {}", pattern.as_str()))
+    }
+}
+
+struct IdentifierLength(usize);
+
+impl Rule for IdentifierLength {
+    fn id(&self) -> &'static str {
+        IDENTIFIER_LENGTH
+    }
+
+    fn checks_parameters(&self) -> bool {
+        true
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        (node.name.chars().count() > self.0)
+            .then(|| format!("name longer than {} characters", self.0))
+    }
+}
+
+struct TestOnlyUsage(GlobSet);
+
+impl Rule for TestOnlyUsage {
+    fn id(&self) -> &'static str {
+        TEST_ONLY_USAGE
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        let only_tests = !node.reference_locations.is_empty()
+            && node
+                .reference_locations
+                .iter()
+                .all(|location| self.0.is_match(&location.file_path));
+        only_tests.then(|| "used only in tests".to_string())
+    }
+}
+
+struct LowUsage(LowUsageRule);
+
+impl Rule for LowUsage {
+    fn id(&self) -> &'static str {
+        LOW_USAGE
+    }
+
+    fn default_severity(&self) -> Severity {
+        self.0.severity
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        self.0
+            .matches(&node.node_type, node.references)
+            .then(|| "low usage".to_string())
+    }
+}
+
+type Metric = fn(&NodeMetrics) -> usize;
+
+/// Flags nodes whose `metric` is above `max`.
+struct SizeLimit {
+    id: &'static str,
+    label: &'static str,
+    metric: Metric,
+    max: usize,
+    limits: SizeLimits,
+}
+
+impl SizeLimit {
+    fn all(limits: &SizeLimits) -> Vec<SizeLimit> {
+        let candidates: [(&'static str, &'static str, Metric, _); 4] = [
+            (
+                TOO_MANY_PROPERTIES,
+                "properties",
+                |m| m.properties,
+                limits.max_properties,
+            ),
+            (
+                TOO_MANY_METHODS,
+                "methods",
+                |m| m.methods,
+                limits.max_methods,
+            ),
+            (TOO_MANY_LINES, "lines", |m| m.lines, limits.max_lines),
+            (
+                NESTING_DEPTH,
+                "nesting levels",
+                |m| m.depth,
+                limits.max_depth,
+            ),
+        ];
+        candidates
+            .into_iter()
+            .filter_map(|(id, label, metric, max)| {
+                max.map(|max| SizeLimit {
+                    id,
+                    label,
+                    metric,
+                    max,
+                    limits: limits.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Rule for SizeLimit {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn check(&self, node: &FullNodeInfo, _parent_name: Option<&str>) -> Option<String> {
+        let value = (self.metric)(&node.metrics);
+        (self.limits.applies_to(&node.node_type) && value > self.max)
+            .then(|| format!("{value} {} (max {})", self.label, self.max))
+    }
+}
+
+struct ConfiguredRule<R: ?Sized> {
+    rule: Box<R>,
+    severity: Severity,
+    threshold: Option<usize>,
+}
+
+/// Number of findings a rule produced over the whole run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSummary {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub count: usize,
+    pub threshold: Option<usize>,
+}
+
+impl RuleSummary {
+    pub fn exceeds_threshold(&self) -> bool {
+        self.threshold
+            .is_some_and(|threshold| self.count > threshold)
+    }
+}
+
+/// The enabled rules with their configured severities and thresholds.
+pub struct RuleSet {
+    rules: Vec<ConfiguredRule<dyn Rule>>,
+    project_rules: Vec<ConfiguredRule<dyn ProjectRule>>,
+}
+
+impl RuleSet {
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let naming = &config.naming;
+        let mut available: Vec<(Box<dyn Rule>, bool)> = vec![
+            (Box::new(DeadCode), true),
+            (
+                Box::new(UselessPrefix(naming.prefix.clone())),
+                naming.prefix.enabled,
+            ),
+            (
+                Box::new(UselessSuffix(naming.prefix.clone())),
+                naming.prefix.enabled && naming.prefix.detect_suffix,
+            ),
+            (Box::new(HungarianInterface), naming.hungarian_interfaces),
+            (
+                Box::new(NamingConvention(naming.conventions.clone())),
+                !naming.conventions.is_empty(),
+            ),
+            (
+                Box::new(TestOnlyUsage(config.test_file_glob.clone())),
+                !config.test_file_glob.is_empty(),
+            ),
+        ];
+        if let Some(max_length) = naming.max_identifier_length {
+            available.push((Box::new(IdentifierLength(max_length)), true));
+        }
+        if config.parameters.is_some() {
+            available.push((Box::new(UnusedParameter), true));
+        }
+        if let Some(low_usage) = &config.low_usage {
+            available.push((Box::new(LowUsage(low_usage.clone())), true));
+        }
+        for limit in SizeLimit::all(&config.size) {
+            available.push((Box::new(limit), true));
+        }
+
+        for id in config.rules.keys() {
+            if !RULE_IDS.contains(&id.as_str()) {
+                bail!("Unknown rule '{id}' in rules configuration");
+            }
+        }
+
+        let rules = available
+            .into_iter()
+            .filter_map(|(rule, enabled_by_default)| {
+                configure(config, rule.id(), enabled_by_default).map(|settings| ConfiguredRule {
+                    severity: settings.severity.unwrap_or_else(|| rule.default_severity()),
+                    threshold: settings.threshold,
+                    rule,
+                })
+            })
+            .collect();
+
+        let mut project_rules: Vec<ConfiguredRule<dyn ProjectRule>> = Vec::new();
+        if let Some(duplicates) = &config.duplicates {
+            let rule = DuplicateNames::new(duplicates.clone(), &config.project_root_path);
+            if let Some(settings) = configure(config, rule.id(), true) {
+                project_rules.push(ConfiguredRule {
+                    severity: settings.severity.unwrap_or_else(|| rule.default_severity()),
+                    threshold: settings.threshold,
+                    rule: Box::new(rule),
+                });
+            }
+        }
+        if let Some(reachability) = &config.reachability {
+            let rule = Unreachable::new(reachability.clone(), config);
+            if let Some(settings) = configure(config, rule.id(), true) {
+                project_rules.push(ConfiguredRule {
+                    severity: settings.severity.unwrap_or_else(|| rule.default_severity()),
+                    threshold: settings.threshold,
+                    rule: Box::new(rule),
+                });
+            }
+        }
+
+        let set = Self {
+            rules,
+            project_rules,
+        };
+        for (id, settings) in &config.rules {
+            if settings.enabled == Some(true) && !set.is_enabled(id) {
+                bail!(
+                    "Rule '{id}' is enabled in rules configuration but needs the `{}` section",
+                    required_section(id)
+                );
+            }
+        }
+        Ok(set)
+    }
+
+    fn is_enabled(&self, id: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|configured| configured.rule.id() == id)
+            || self
+                .project_rules
+                .iter()
+                .any(|configured| configured.rule.id() == id)
+    }
+
+    /// Replaces the findings of `nodes` and their children with fresh rule results.
+    pub fn apply(&self, nodes: &mut [FullNodeInfo], parent_name: Option<&str>) {
+        for node in nodes {
+            let parameter = node.is_parameter();
+            node.findings = self
+                .rules
+                .iter()
+                .filter(|configured| !parameter || configured.rule.checks_parameters())
+                .filter_map(|configured| {
+                    configured
+                        .rule
+                        .check(node, parent_name)
+                        .map(|message| Finding {
+                            rule: configured.rule.id().to_string(),
+                            severity: configured.severity,
+                            message,
+                        })
+                })
+                .collect();
+            let name = node.name.clone();
+            self.apply(&mut node.children, Some(&name));
+        }
+    }
+
+    /// Whether a rule needs every analysed file, e.g. to compare names across files.
+    pub fn has_project_rules(&self) -> bool {
+        !self.project_rules.is_empty()
+    }
+
+    /// Replaces the project-level findings of `nodes`, so it can run again after a change.
+    pub fn apply_project(&self, nodes: &mut [FullNodeInfo]) {
+        let ids: Vec<_> = self
+            .project_rules
+            .iter()
+            .map(|configured| configured.rule.id())
+            .collect();
+        remove_findings(nodes, &ids);
+        for configured in &self.project_rules {
+            for (path, message) in configured.rule.check(nodes) {
+                let (&first, rest) = path.split_first().expect("finding without entity");
+                let node = rest
+                    .iter()
+                    .fold(&mut nodes[first], |node, &index| &mut node.children[index]);
+                node.findings.push(Finding {
+                    rule: configured.rule.id().to_string(),
+                    severity: configured.severity,
+                    message,
+                });
+            }
+        }
+    }
+
+    pub fn summarize(&self, nodes: &[FullNodeInfo]) -> Vec<RuleSummary> {
+        let node_rules = self.rules.iter().map(|configured| {
+            (
+                configured.rule.id(),
+                configured.severity,
+                configured.threshold,
+            )
+        });
+        let project_rules = self.project_rules.iter().map(|configured| {
+            (
+                configured.rule.id(),
+                configured.severity,
+                configured.threshold,
+            )
+        });
+        node_rules
+            .chain(project_rules)
+            .map(|(id, severity, threshold)| RuleSummary {
+                id,
+                severity,
+                count: count_findings(nodes, id),
+                threshold,
+            })
+            .collect()
+    }
+}
+
+fn remove_findings(nodes: &mut [FullNodeInfo], ids: &[&str]) {
+    for node in nodes {
+        node.findings
+            .retain(|finding| !ids.contains(&finding.rule.as_str()));
+        remove_findings(&mut node.children, ids);
+    }
+}
+
+/// Settings of rule `id`, or `None` when the rule is disabled.
+/// Configuration section providing the settings of rule `id`.
+fn required_section(id: &str) -> &'static str {
+    match id {
+        IDENTIFIER_LENGTH => "naming.maxIdentifierLength",
+        TEST_ONLY_USAGE => "testFileGlobs",
+        LOW_USAGE => "lowUsage",
+        TOO_MANY_PROPERTIES => "size.maxProperties",
+        TOO_MANY_METHODS => "size.maxMethods",
+        TOO_MANY_LINES => "size.maxLines",
+        NESTING_DEPTH => "size.maxDepth",
+        DUPLICATE_NAME => "duplicates",
+        UNUSED_PARAMETER => "parameters",
+        UNREACHABLE => "reachability",
+        NAMING_CONVENTION => "naming.conventions",
+        _ => "naming",
+    }
+}
+
+fn configure(config: &AppConfig, id: &str, enabled_by_default: bool) -> Option<RuleSettings> {
+    let settings = config.rules.get(id).cloned().unwrap_or_default();
+    settings
+        .enabled
+        .unwrap_or(enabled_by_default)
+        .then_some(settings)
+}
+
+/// Entities carrying a finding of rule `id`, children included.
+pub fn count_findings(nodes: &[FullNodeInfo], id: &str) -> usize {
+    nodes
+        .iter()
+        .map(|node| {
+            let current = node.findings.iter().filter(|f| f.rule == id).count();
+            current + count_findings(&node.children, id)
+        })
+        .sum()
+}
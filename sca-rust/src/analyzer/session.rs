@@ -0,0 +1,211 @@
+use super::transport::{BoxFuture, Transport};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionEntry {
+    direction: Direction,
+    message: Value,
+}
+
+/// Appends every JSON-RPC message exchanged with the server to a JSON Lines file.
+pub struct SessionRecorder {
+    file: File,
+}
+
+impl SessionRecorder {
+    pub async fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .await
+            .with_context(|| format!("Failed to create LSP record file {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub async fn record(&mut self, direction: Direction, message: &Value) -> Result<()> {
+        let entry = SessionEntry {
+            direction,
+            message: message.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Wraps a transport and logs every message passing through it.
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    recorder: SessionRecorder,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn Transport>, recorder: SessionRecorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send<'a>(&'a mut self, message: &'a Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.recorder.record(Direction::Send, message).await?;
+            self.inner.send(message).await
+        })
+    }
+
+    fn receive(&mut self) -> BoxFuture<'_, Result<Value>> {
+        Box::pin(async move {
+            let message = self.inner.receive().await?;
+            self.recorder.record(Direction::Receive, &message).await?;
+            Ok(message)
+        })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        self.inner.close()
+    }
+}
+
+/// Serves server messages from a previously recorded session instead of a live process.
+pub struct SessionReplay {
+    path: PathBuf,
+    sent: VecDeque<Value>,
+    received: VecDeque<Value>,
+}
+
+impl SessionReplay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read LSP replay file {}", path.display()))?;
+
+        let mut sent = VecDeque::new();
+        let mut received = VecDeque::new();
+
+        for (idx, line) in raw.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: SessionEntry = serde_json::from_str(line)
+                .with_context(|| format!("Invalid entry at {}:{}", path.display(), idx + 1))?;
+            match entry.direction {
+                Direction::Send => sent.push_back(entry.message),
+                Direction::Receive => received.push_back(entry.message),
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sent,
+            received,
+        })
+    }
+
+    /// Checks an outgoing message against the recording; divergence is logged, not fatal,
+    /// so a replay keeps working when only request payloads drift.
+    pub fn accept(&mut self, message: &Value) {
+        let Some(expected) = self.sent.pop_front() else {
+            tracing::warn!(
+                "Replay {} has no more recorded client messages, got {}",
+                self.path.display(),
+                method_of(message)
+            );
+            return;
+        };
+        if method_of(&expected) != method_of(message) {
+            tracing::warn!(
+                "Replay {} diverged: expected {}, got {}",
+                self.path.display(),
+                method_of(&expected),
+                method_of(message)
+            );
+        }
+    }
+
+    pub fn next_message(&mut self) -> Result<Value> {
+        match self.received.pop_front() {
+            Some(message) => Ok(message),
+            None => bail!("LSP replay {} is exhausted", self.path.display()),
+        }
+    }
+
+    pub fn ensure_consumed(&self) -> Result<()> {
+        if self.received.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "LSP replay {} has {} unread server messages",
+                self.path.display(),
+                self.received.len()
+            ))
+        }
+    }
+}
+
+impl Transport for SessionReplay {
+    fn send<'a>(&'a mut self, message: &'a Value) -> BoxFuture<'a, Result<()>> {
+        self.accept(message);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn receive(&mut self) -> BoxFuture<'_, Result<Value>> {
+        let message = self.next_message();
+        Box::pin(async move { message })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        if let Err(err) = self.ensure_consumed() {
+            tracing::warn!("{err}");
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn method_of(message: &Value) -> &str {
+    message
+        .get("method")
+        .and_then(Value::as_str)
+        .unwrap_or("<response>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn replays_recorded_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"});
+        let response = json!({"jsonrpc": "2.0", "id": 1, "result": {}});
+
+        let mut recorder = SessionRecorder::create(&path).await.unwrap();
+        recorder.record(Direction::Send, &request).await.unwrap();
+        recorder
+            .record(Direction::Receive, &response)
+            .await
+            .unwrap();
+        drop(recorder);
+
+        let mut replay = SessionReplay::load(&path).unwrap();
+        replay.accept(&request);
+        assert_eq!(replay.next_message().unwrap(), response);
+        assert!(replay.ensure_consumed().is_ok());
+        assert!(replay.next_message().is_err());
+    }
+}
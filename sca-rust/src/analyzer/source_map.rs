@@ -0,0 +1,188 @@
+use super::rules::DEAD_CODE;
+use crate::model::{FullNodeInfo, NodeInfo};
+use lsp_types::Position;
+use tree_sitter::Point;
+
+/// An analysed entity paired with its declaration in the raw file.
+///
+/// Analysis runs on sanitized sources whose rows differ from the file on disk, so edits
+/// and diagnostics must use the declaration parsed from the raw source instead.
+pub struct Located<'a> {
+    pub node: &'a FullNodeInfo,
+    pub declaration: &'a NodeInfo,
+    pub parent_name: Option<&'a str>,
+}
+
+pub fn has_finding(node: &FullNodeInfo, rule: &str) -> bool {
+    node.findings.iter().any(|finding| finding.rule == rule)
+}
+
+/// Pairs analysed nodes with raw declarations by node type, name and position among their
+/// siblings; dead nodes are not descended into since their children go away with them.
+pub fn locate<'a>(
+    nodes: &'a [FullNodeInfo],
+    declarations: &'a [NodeInfo],
+    parent_name: Option<&'a str>,
+    located: &mut Vec<Located<'a>>,
+) {
+    for (index, node) in nodes.iter().enumerate() {
+        let occurrence = nodes[..index]
+            .iter()
+            .filter(|other| other.node_type == node.node_type && other.name == node.name)
+            .count();
+        let Some(declaration) = declarations
+            .iter()
+            .filter(|other| other.node_type == node.node_type && other.name == node.name)
+            .nth(occurrence)
+        else {
+            tracing::warn!(
+                "Unable to locate {} in {}, leaving it in place",
+                node.name,
+                node.file_path.display()
+            );
+            continue;
+        };
+
+        located.push(Located {
+            node,
+            declaration,
+            parent_name,
+        });
+        if !has_finding(node, DEAD_CODE) {
+            locate(
+                &node.children,
+                &declaration.children,
+                Some(&node.name),
+                located,
+            );
+        }
+    }
+}
+
+/// Maps points of a sanitized source back to the file it was sanitized from.
+pub struct RawPoints {
+    sanitized_lines: Vec<usize>,
+    raw_lines: Vec<usize>,
+    offsets: Vec<usize>,
+}
+
+impl RawPoints {
+    /// `offsets` are those returned with `sanitized` by `sanitize_with_offsets(raw)`.
+    pub fn new(raw: &str, sanitized: &str, offsets: Vec<usize>) -> Self {
+        Self {
+            sanitized_lines: line_starts(sanitized),
+            raw_lines: line_starts(raw),
+            offsets,
+        }
+    }
+
+    pub fn to_raw(&self, point: Point) -> Point {
+        let line_start = self
+            .sanitized_lines
+            .get(point.row)
+            .copied()
+            .unwrap_or_default();
+        let offset = (line_start + point.column).min(self.offsets.len() - 1);
+        let raw = self.offsets[offset];
+        let row = self.raw_lines.partition_point(|&start| start <= raw) - 1;
+        Point {
+            row,
+            column: raw - self.raw_lines[row],
+        }
+    }
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(newline, _)| newline + 1))
+        .collect()
+}
+
+/// LSP positions count UTF-16 code units, tree-sitter columns count bytes.
+pub fn to_lsp_position(source: &str, point: Point) -> Position {
+    let line = source.lines().nth(point.row).unwrap_or_default();
+    let prefix = line.get(..point.column).unwrap_or(line);
+    Position {
+        line: point.row as u32,
+        character: prefix.encode_utf16().count() as u32,
+    }
+}
+
+/// Tree-sitter point of an LSP position, the inverse of [`to_lsp_position`].
+pub fn to_point(source: &str, position: Position) -> Point {
+    let line = source
+        .lines()
+        .nth(position.line as usize)
+        .unwrap_or_default();
+    let mut units = 0;
+    let column = line
+        .char_indices()
+        .find(|(_, character)| {
+            let reached = units >= position.character as usize;
+            units += character.len_utf16();
+            reached
+        })
+        .map_or(line.len(), |(offset, _)| offset);
+    Point {
+        row: position.line as usize,
+        column,
+    }
+}
+
+pub fn offset_to_position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+pub fn to_offset(source: &str, position: Position) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = source[line_start..].lines().next().unwrap_or_default();
+    let mut units = 0;
+    for (offset, character) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + offset;
+        }
+        units += character.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_utf16_positions_and_offsets() {
+        let source = "const a = 1;\nconst имя = userName;\n";
+        let position = Position {
+            line: 1,
+            character: 12,
+        };
+        let offset = to_offset(source, position);
+        assert_eq!(&source[offset..], "userName;\n");
+        assert_eq!(offset_to_position(source, offset), position);
+        assert_eq!(
+            to_lsp_position(source, Point { row: 1, column: 15 }),
+            position
+        );
+    }
+
+    #[test]
+    fn maps_sanitized_points_to_the_raw_file() {
+        let raw = "/* header\n */\nexport interface User {}\n";
+        let (sanitized, offsets) = crate::sanitize::sanitize_with_offsets(raw);
+        let points = RawPoints::new(raw, &sanitized, offsets);
+        assert_eq!(
+            points.to_raw(Point { row: 1, column: 17 }),
+            Point { row: 2, column: 17 }
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::model::FullNodeInfo;
+use crate::sanitize::sanitize_source;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    static ref NEXT_LINE: Regex = Regex::new(r"//\s*sca-ignore-next-line\b([^\r\n]*)").unwrap();
+    static ref FILE: Regex =
+        Regex::new(r"(?://|/\*)\s*sca-ignore-file\b([^\r\n]*?)(?:\*/|\r?\n|$)").unwrap();
+}
+
+/// Comment directive silencing findings of some rules (all rules when `rules` is empty).
+#[derive(Debug, Clone)]
+struct Directive {
+    /// Row of the directive in the raw source.
+    row: usize,
+    /// Row in the sanitized source the directive applies to; `None` for the whole file.
+    target_row: Option<usize>,
+    rules: Vec<String>,
+    used: bool,
+}
+
+impl Directive {
+    fn covers(&self, rule: &str) -> bool {
+        self.rules.is_empty() || self.rules.iter().any(|r| r == rule)
+    }
+
+    fn text(&self) -> String {
+        let kind = match self.target_row {
+            Some(_) => "sca-ignore-next-line",
+            None => "sca-ignore-file",
+        };
+        if self.rules.is_empty() {
+            kind.to_string()
+        } else {
+            format!("{kind} {}", self.rules.join(", "))
+        }
+    }
+}
+
+/// A directive that did not silence any finding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnusedSuppression {
+    pub file_path: PathBuf,
+    pub row: usize,
+    pub directive: String,
+}
+
+/// Suppression directives of a single file, collected from the raw source.
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    directives: Vec<Directive>,
+}
+
+impl Suppressions {
+    /// Must run on the raw source: `sanitize_source` strips the comments holding directives.
+    pub fn parse(source: &str) -> Self {
+        let mut directives = Vec::new();
+
+        for captures in NEXT_LINE.captures_iter(source) {
+            let directive = captures.get(0).unwrap();
+            let line_end = source[directive.end()..]
+                .find('\n')
+                .map(|offset| directive.end() + offset + 1)
+                .unwrap_or(source.len());
+            directives.push(Directive {
+                row: row_of(source, directive.start()),
+                // Sanitizing drops block comments and console calls, which shifts rows.
+                target_row: Some(sanitize_source(&source[..line_end]).matches('\n').count()),
+                rules: parse_rules(&captures[1]),
+                used: false,
+            });
+        }
+
+        for captures in FILE.captures_iter(source) {
+            directives.push(Directive {
+                row: row_of(source, captures.get(0).unwrap().start()),
+                target_row: None,
+                rules: parse_rules(&captures[1]),
+                used: false,
+            });
+        }
+
+        Self { directives }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// Removes suppressed findings from `nodes` and their children.
+    pub fn apply(&mut self, nodes: &mut [FullNodeInfo]) {
+        for node in nodes {
+            // Directives sit above decorators and multi-line signatures, not the name.
+            let row = node.range.start.row;
+            node.findings.retain(|finding| {
+                let directive = self.directives.iter_mut().find(|directive| {
+                    directive.target_row.is_none_or(|target| target == row)
+                        && directive.covers(&finding.rule)
+                });
+                match directive {
+                    Some(directive) => {
+                        directive.used = true;
+                        false
+                    }
+                    None => true,
+                }
+            });
+            self.apply(&mut node.children);
+        }
+    }
+
+    pub fn unused(&self, path: &Path) -> Vec<UnusedSuppression> {
+        self.directives
+            .iter()
+            .filter(|directive| !directive.used)
+            .map(|directive| UnusedSuppression {
+                file_path: path.to_path_buf(),
+                row: directive.row,
+                directive: directive.text(),
+            })
+            .collect()
+    }
+}
+
+fn parse_rules(raw: &str) -> Vec<String> {
+    raw.split(|c: char| c == ',' || c.is_whitespace())
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty() && *rule != "*/")
+        .map(str::to_string)
+        .collect()
+}
+
+fn row_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Finding, Severity, SourceRange};
+    use tree_sitter::Point;
+
+    fn node(name: &str, row: usize, rules: &[&str]) -> FullNodeInfo {
+        FullNodeInfo {
+            node_type: "interface_declaration".to_string(),
+            name: name.to_string(),
+            start_position: Point { row, column: 0 },
+            file_path: "src/models.ts".into(),
+            exported: false,
+            references: 0,
+            reference_locations: vec![],
+            findings: rules
+                .iter()
+                .map(|rule| Finding {
+                    rule: rule.to_string(),
+                    severity: Severity::Error,
+                    message: rule.to_string(),
+                })
+                .collect(),
+            metrics: Default::default(),
+            range: SourceRange {
+                start: Point { row, column: 0 },
+                end: Point { row, column: 0 },
+            },
+            raw_range: Default::default(),
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn silences_next_line_after_removed_block_comments() {
+        let source = "/**\n * Docs\n */\n// sca-ignore-next-line dead-code\nexport interface User {}\n// sca-ignore-next-line useless-prefix\nexport interface Item {}\n";
+        let mut suppressions = Suppressions::parse(source);
+        // The doc comment is collapsed before parsing, so `User` sits on row 2 instead of 4.
+        let mut nodes = vec![
+            node("User", 2, &["dead-code", "useless-prefix"]),
+            node("Item", 4, &["dead-code"]),
+        ];
+
+        suppressions.apply(&mut nodes);
+
+        assert_eq!(nodes[0].findings.len(), 1);
+        assert_eq!(nodes[0].findings[0].rule, "useless-prefix");
+        assert_eq!(nodes[1].findings.len(), 1);
+        assert_eq!(
+            suppressions.unused(Path::new("src/models.ts")),
+            vec![UnusedSuppression {
+                file_path: "src/models.ts".into(),
+                row: 5,
+                directive: "sca-ignore-next-line useless-prefix".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn silences_whole_file() {
+        let mut suppressions =
+            Suppressions::parse("/* sca-ignore-file */\nexport interface User {}\n");
+        let mut nodes = vec![node("User", 1, &["dead-code"])];
+        nodes[0].children.push(node("name", 2, &["dead-code"]));
+
+        suppressions.apply(&mut nodes);
+
+        assert!(nodes[0].findings.is_empty());
+        assert!(nodes[0].children[0].findings.is_empty());
+        assert!(suppressions.unused(Path::new("a.ts")).is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::config::{AppConfig, LspTransport};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::process::Stdio;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::task::JoinHandle;
+
+/// Future returned by transports and [`ReferenceProvider`](crate::ReferenceProvider) methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Moves whole JSON-RPC messages between the client and a language server.
+pub trait Transport: Send {
+    /// Writes one message.
+    fn send<'a>(&'a mut self, message: &'a Value) -> BoxFuture<'a, Result<()>>;
+
+    /// Waits for the next message.
+    fn receive(&mut self) -> BoxFuture<'_, Result<Value>>;
+
+    /// Releases the underlying resources once `exit` has been sent.
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Content-Length framing over any byte stream pair: pipes, sockets or in-memory duplexes.
+pub struct FramedTransport<R, W> {
+    reader: BufReader<R>,
+    writer: W,
+}
+
+impl<R, W> FramedTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    /// Reads framed messages from `reader` and writes them to `writer`.
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+}
+
+impl<R, W> Transport for FramedTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    fn send<'a>(&'a mut self, message: &'a Value) -> BoxFuture<'a, Result<()>> {
+        Box::pin(write_frame(&mut self.writer, message))
+    }
+
+    fn receive(&mut self) -> BoxFuture<'_, Result<Value>> {
+        Box::pin(read_frame(&mut self.reader))
+    }
+}
+
+/// Stdio transport of a spawned language server process.
+pub(crate) struct ProcessTransport {
+    child: Child,
+    framed: FramedTransport<ChildStdout, ChildStdin>,
+    stderr_task: Option<JoinHandle<()>>,
+}
+
+impl ProcessTransport {
+    pub(crate) fn spawn(config: &AppConfig) -> Result<Self> {
+        let mut command = Command::new(&config.lsp_executable);
+        command.args(&config.lsp_args);
+        command.stdin(Stdio::piped());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+        command.current_dir(&config.project_root_path);
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to spawn {}", config.lsp_executable))?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("LSP process stdin not available"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("LSP process stdout not available"))?;
+
+        let stderr = child.stderr.take();
+        let stderr_task = stderr.map(spawn_stderr_logger);
+
+        Ok(Self {
+            child,
+            framed: FramedTransport::new(stdout, stdin),
+            stderr_task,
+        })
+    }
+}
+
+impl Transport for ProcessTransport {
+    fn send<'a>(&'a mut self, message: &'a Value) -> BoxFuture<'a, Result<()>> {
+        self.framed.send(message)
+    }
+
+    fn receive(&mut self) -> BoxFuture<'_, Result<Value>> {
+        self.framed.receive()
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            if let Some(handle) = self.stderr_task.take() {
+                handle.abort();
+            }
+            let status = self.child.wait().await?;
+            if !status.success() {
+                tracing::warn!("LSP process exited with {:?}", status);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Drop for ProcessTransport {
+    fn drop(&mut self) {
+        if let Some(handle) = self.stderr_task.take() {
+            handle.abort();
+        }
+        let _ = self.child.start_kill();
+    }
+}
+
+/// Opens the transport selected by `lspTransport`.
+pub(crate) async fn connect(config: &AppConfig) -> Result<Box<dyn Transport>> {
+    match &config.lsp_transport {
+        LspTransport::Stdio => Ok(Box::new(ProcessTransport::spawn(config)?)),
+        LspTransport::Tcp(address) => {
+            let stream = TcpStream::connect(address)
+                .await
+                .with_context(|| format!("Failed to connect to LSP server at {address}"))?;
+            let (reader, writer) = stream.into_split();
+            Ok(Box::new(FramedTransport::new(reader, writer)))
+        }
+        #[cfg(unix)]
+        LspTransport::Unix(path) => {
+            let stream = UnixStream::connect(path).await.with_context(|| {
+                format!("Failed to connect to LSP server at {}", path.display())
+            })?;
+            let (reader, writer) = stream.into_split();
+            Ok(Box::new(FramedTransport::new(reader, writer)))
+        }
+        #[cfg(not(unix))]
+        LspTransport::Unix(_) => bail!("Unix socket transport is not supported on this platform"),
+    }
+}
+
+fn spawn_stderr_logger(stderr: ChildStderr) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let reader = BufReader::new(stderr);
+        let mut lines = reader.lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::debug!("LSP stderr: {}", line);
+        }
+    })
+}
+
+async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), value: &Value) -> Result<()> {
+    let body = serde_json::to_vec(value)?;
+    let header = format!("Content-Length: {}\r\n\r\n", body.len());
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn read_frame(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Value> {
+    let mut content_length: Option<usize> = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await?;
+        if read == 0 {
+            bail!("LSP server closed the stream");
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(value) = trimmed.strip_prefix("Content-Length:") {
+            let len = value
+                .trim()
+                .parse::<usize>()
+                .context("Invalid Content-Length")?;
+            content_length = Some(len);
+        }
+    }
+
+    let length = content_length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer).await?;
+    serde_json::from_slice(&buffer).context("Invalid JSON payload")
+}
@@ -1,15 +1,39 @@
-use crate::config::Grammar;
-use crate::model::{NodeInfo, NodeTarget};
+use crate::config::{Grammar, ParameterRule};
+use crate::model::{NodeInfo, NodeMetrics, NodeTarget, SourceRange, PARAMETER_KINDS};
 use anyhow::{Context, Result};
-use tree_sitter::{Language, Node, Parser};
+use std::ops::Range;
+use tree_sitter::{Language, Node, Parser, Point};
+
+const PROPERTY_KINDS: [&str; 3] = [
+    "public_field_definition",
+    "property_signature",
+    "index_signature",
+];
+const METHOD_KINDS: [&str; 3] = [
+    "method_definition",
+    "method_signature",
+    "abstract_method_signature",
+];
+const BLOCK_KIND: &str = "statement_block";
+const EXPORT_KIND: &str = "export_statement";
+const COMMENT_KIND: &str = "comment";
+const DECORATOR_KIND: &str = "decorator";
+const DECLARATOR_KIND: &str = "variable_declarator";
+const DECLARATION_KINDS: [&str; 2] = ["lexical_declaration", "variable_declaration"];
+const BODY_KINDS: [&str; 4] = ["class_body", "interface_body", "object_type", "enum_body"];
 
 pub struct TreeAnalyzer {
     parser: Parser,
     reference_nodes: Vec<NodeTarget>,
+    parameters: Option<ParameterRule>,
 }
 
 impl TreeAnalyzer {
-    pub fn new(grammar: Grammar, reference_nodes: Vec<NodeTarget>) -> Result<Self> {
+    pub fn new(
+        grammar: Grammar,
+        reference_nodes: Vec<NodeTarget>,
+        parameters: Option<ParameterRule>,
+    ) -> Result<Self> {
         let mut parser = Parser::new();
         parser
             .set_language(language_from_grammar(grammar))
@@ -18,6 +42,7 @@ impl TreeAnalyzer {
         Ok(Self {
             parser,
             reference_nodes,
+            parameters,
         })
     }
 
@@ -37,6 +62,46 @@ impl TreeAnalyzer {
         Ok(found)
     }
 
+    /// Names of every member declared next to the one named at `position`, whether or not
+    /// it is a configured reference node.
+    pub fn member_names(&mut self, source: &str, position: Point) -> Result<Vec<String>> {
+        let tree = self
+            .parser
+            .parse(source, None)
+            .context("tree-sitter failed to parse source")?;
+
+        let mut body = tree
+            .root_node()
+            .descendant_for_point_range(position, position);
+        while let Some(node) = body {
+            if BODY_KINDS.contains(&node.kind()) {
+                break;
+            }
+            body = node.parent();
+        }
+        let Some(body) = body else {
+            return Ok(Vec::new());
+        };
+
+        let mut cursor = body.walk();
+        let names = body
+            .named_children(&mut cursor)
+            .filter_map(|member| {
+                let name = member.child_by_field_name("name").unwrap_or(member);
+                matches!(
+                    name.kind(),
+                    "property_identifier" | "private_property_identifier" | "identifier"
+                )
+                .then(|| {
+                    name.utf8_text(source.as_bytes())
+                        .unwrap_or_default()
+                        .to_string()
+                })
+            })
+            .collect();
+        Ok(names)
+    }
+
     fn handle_node(&self, source: &[u8], node: Node<'_>) -> Result<Vec<NodeInfo>> {
         let mut matched = Vec::new();
         for target in &self.reference_nodes {
@@ -70,21 +135,266 @@ impl TreeAnalyzer {
             .trim()
             .to_string();
 
-        let children = if target.children.is_empty() {
+        let mut children = if target.children.is_empty() {
             Vec::new()
         } else {
             self.collect_children(source, node, &target.children)?
         };
+        if let Some(rule) = &self.parameters {
+            children.extend(collect_parameters(rule, source, node));
+        }
 
         Ok(Some(NodeInfo {
             node_type: node.kind().to_string(),
             name,
             start_position: target_node.start_position(),
+            range: SourceRange {
+                start: statement_start(node),
+                end: node.end_position(),
+            },
+            exported: node
+                .parent()
+                .is_some_and(|parent| parent.kind() == EXPORT_KIND),
+            removal: removal_range(source, node),
+            metrics: measure(node),
             children,
         }))
     }
 }
 
+/// Parameters declared by `function` itself, destructured ones split into their fields.
+fn collect_parameters(rule: &ParameterRule, source: &[u8], function: Node<'_>) -> Vec<NodeInfo> {
+    let Some(parameters) = function.child_by_field_name("parameters") else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    let mut cursor = parameters.walk();
+    for parameter in parameters.named_children(&mut cursor) {
+        if !PARAMETER_KINDS.contains(&parameter.kind()) || is_parameter_property(parameter) {
+            continue;
+        }
+        let Some(pattern) = parameter
+            .child_by_field_name("pattern")
+            .or_else(|| parameter.child_by_field_name("name"))
+        else {
+            continue;
+        };
+        match pattern.kind() {
+            "identifier" => found.push(parameter_node(source, parameter, pattern)),
+            "rest_pattern" => {
+                if let Some(name) = find_descendant(pattern, "identifier") {
+                    found.push(parameter_node(source, parameter, name));
+                }
+            }
+            "object_pattern" => {
+                let mut fields = pattern.walk();
+                for field in pattern.named_children(&mut fields) {
+                    let name = match field.kind() {
+                        "shorthand_property_identifier_pattern" => Some(field),
+                        "pair_pattern" => field.child_by_field_name("value").and_then(|value| {
+                            match value.kind() {
+                                "identifier" => Some(value),
+                                "assignment_pattern" => value
+                                    .child_by_field_name("left")
+                                    .filter(|left| left.kind() == "identifier"),
+                                _ => None,
+                            }
+                        }),
+                        "object_assignment_pattern" => field
+                            .child_by_field_name("left")
+                            .filter(|left| left.kind() == "shorthand_property_identifier_pattern"),
+                        _ => None,
+                    };
+                    if let Some(name) = name {
+                        found.push(parameter_node(source, field, name));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found.retain(|node| !(rule.ignore_underscore && node.name.starts_with('_')));
+    found
+}
+
+/// `constructor(private readonly repo: Repo)` declares a class property, not a parameter.
+fn is_parameter_property(parameter: Node<'_>) -> bool {
+    let mut cursor = parameter.walk();
+    let declares_property = parameter
+        .children(&mut cursor)
+        .any(|child| matches!(child.kind(), "accessibility_modifier" | "readonly"));
+    declares_property
+}
+
+fn parameter_node(source: &[u8], node: Node<'_>, name: Node<'_>) -> NodeInfo {
+    NodeInfo {
+        node_type: node.kind().to_string(),
+        name: name.utf8_text(source).unwrap_or_default().to_string(),
+        start_position: name.start_position(),
+        range: SourceRange {
+            start: node.start_position(),
+            end: node.end_position(),
+        },
+        exported: false,
+        removal: node.byte_range(),
+        metrics: NodeMetrics::default(),
+        children: Vec::new(),
+    }
+}
+
+/// Where the statement declaring `node` begins: its `export` keyword or first decorator.
+fn statement_start(node: Node<'_>) -> Point {
+    let mut start = node
+        .parent()
+        .filter(|parent| parent.kind() == EXPORT_KIND)
+        .unwrap_or(node);
+    // Decorators of class members are siblings rather than children of the member.
+    while let Some(previous) = start
+        .prev_sibling()
+        .filter(|previous| previous.kind() == DECORATOR_KIND)
+    {
+        start = previous;
+    }
+    start.start_position()
+}
+
+fn removal_range(source: &[u8], node: Node<'_>) -> Range<usize> {
+    let mut node = node;
+    if node.kind() == DECLARATOR_KIND {
+        if let Some(declaration) = node
+            .parent()
+            .filter(|parent| DECLARATION_KINDS.contains(&parent.kind()))
+        {
+            let mut cursor = declaration.walk();
+            let declarators = declaration
+                .named_children(&mut cursor)
+                .filter(|child| child.kind() == DECLARATOR_KIND)
+                .count();
+            if declarators > 1 {
+                return declarator_range(node);
+            }
+            node = declaration;
+        }
+    }
+    let node = node
+        .parent()
+        .filter(|parent| parent.kind() == EXPORT_KIND)
+        .unwrap_or(node);
+
+    let mut start = node;
+    while let Some(previous) = start.prev_sibling() {
+        let adjacent = previous.end_position().row + 1 >= start.start_position().row;
+        if previous.kind() != COMMENT_KIND
+            || !adjacent
+            || !is_blank(&source[line_start(source, previous.start_byte())..previous.start_byte()])
+        {
+            break;
+        }
+        start = previous;
+    }
+
+    let mut begin = start.start_byte();
+    let indent = line_start(source, begin);
+    if is_blank(&source[indent..begin]) {
+        begin = indent;
+    }
+
+    let mut end = node.end_byte();
+    if matches!(source.get(end), Some(b';' | b',')) {
+        end += 1;
+    }
+    let line_end = next_line(source, end);
+    if is_blank(&source[end..line_end]) {
+        end = line_end;
+        let following = next_line(source, end);
+        if following > end && is_blank(&source[end..following]) {
+            end = following;
+        }
+    }
+    // At the end of the file the separating blank lines above would dangle instead.
+    if end == source.len() && begin == line_start(source, begin) {
+        while begin > 0 {
+            let previous = line_start(source, begin - 1);
+            if !is_blank(&source[previous..begin]) {
+                break;
+            }
+            begin = previous;
+        }
+    }
+    begin..end
+}
+
+/// One of several declarators of `const a = 1, b = 2;` with the comma separating it from
+/// the next one, or from the previous one when it is the last.
+fn declarator_range(node: Node<'_>) -> Range<usize> {
+    match (node.next_sibling(), node.prev_sibling()) {
+        (Some(comma), _) if comma.kind() == "," => {
+            let end = comma
+                .next_named_sibling()
+                .map_or(comma.end_byte(), |next| next.start_byte());
+            node.start_byte()..end
+        }
+        (_, Some(comma)) if comma.kind() == "," => {
+            let start = comma
+                .prev_named_sibling()
+                .map_or(comma.start_byte(), |previous| previous.end_byte());
+            start..node.end_byte()
+        }
+        _ => node.byte_range(),
+    }
+}
+
+fn line_start(source: &[u8], offset: usize) -> usize {
+    source[..offset]
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1)
+}
+
+/// Offset just past the newline ending the line at `offset`.
+fn next_line(source: &[u8], offset: usize) -> usize {
+    source[offset..]
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(source.len(), |newline| offset + newline + 1)
+}
+
+fn is_blank(bytes: &[u8]) -> bool {
+    bytes.iter().all(u8::is_ascii_whitespace)
+}
+
+fn measure(node: Node<'_>) -> NodeMetrics {
+    let body = node
+        .child_by_field_name("body")
+        .or_else(|| node.child_by_field_name("value"));
+    let mut metrics = NodeMetrics {
+        lines: node.end_position().row - node.start_position().row + 1,
+        depth: block_depth(node),
+        ..NodeMetrics::default()
+    };
+    if let Some(body) = body {
+        let mut cursor = body.walk();
+        for member in body.named_children(&mut cursor) {
+            if PROPERTY_KINDS.contains(&member.kind()) {
+                metrics.properties += 1;
+            } else if METHOD_KINDS.contains(&member.kind()) {
+                metrics.methods += 1;
+            }
+        }
+    }
+    metrics
+}
+
+fn block_depth(node: Node<'_>) -> usize {
+    let mut cursor = node.walk();
+    let deepest = node
+        .named_children(&mut cursor)
+        .map(block_depth)
+        .max()
+        .unwrap_or(0);
+    deepest + usize::from(node.kind() == BLOCK_KIND)
+}
+
 fn language_from_grammar(grammar: Grammar) -> Language {
     match grammar {
         Grammar::TypeScript => tree_sitter_typescript::language_typescript(),
@@ -175,3 +485,137 @@ fn traverse_result(node: Node<'_>, mut visit: impl FnMut(Node<'_>) -> Result<()>
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_members_lines_and_nesting() {
+        let target = NodeTarget {
+            node_type: "class_declaration".to_string(),
+            ref_type: Some("type_identifier".to_string()),
+            children: vec![],
+        };
+        let mut analyzer = TreeAnalyzer::new(Grammar::TypeScript, vec![target], None).unwrap();
+        let source = "class Cache {\n  size = 0;\n  items: string[] = [];\n  clear() {\n    if (this.size) {\n      this.items = [];\n    }\n  }\n}\n";
+
+        let nodes = analyzer.find_positions(source).unwrap();
+
+        assert_eq!(
+            nodes[0].metrics,
+            NodeMetrics {
+                properties: 2,
+                methods: 1,
+                lines: 9,
+                depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn collects_parameters_and_destructured_fields() {
+        let target = NodeTarget {
+            node_type: "function_declaration".to_string(),
+            ref_type: Some("identifier".to_string()),
+            children: vec![],
+        };
+        let rule = ParameterRule {
+            ignore_underscore: true,
+        };
+        let mut analyzer =
+            TreeAnalyzer::new(Grammar::TypeScript, vec![target], Some(rule)).unwrap();
+        let source = "function render(id: string, { title, size: width = 1, ...rest }: Props, _ctx?: Ctx, ...items: Item[]) {}\n";
+
+        let nodes = analyzer.find_positions(source).unwrap();
+
+        let names: Vec<_> = nodes[0]
+            .children
+            .iter()
+            .map(|child| (child.node_type.as_str(), child.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("required_parameter", "id"),
+                ("shorthand_property_identifier_pattern", "title"),
+                ("pair_pattern", "width"),
+                ("required_parameter", "items"),
+            ]
+        );
+    }
+
+    #[test]
+    fn removal_covers_export_doc_comment_and_blank_line() {
+        let target = NodeTarget {
+            node_type: "interface_declaration".to_string(),
+            ref_type: Some("type_identifier".to_string()),
+            children: vec![NodeTarget {
+                node_type: "property_signature".to_string(),
+                ref_type: Some("property_identifier".to_string()),
+                children: vec![],
+            }],
+        };
+        let mut analyzer = TreeAnalyzer::new(Grammar::TypeScript, vec![target], None).unwrap();
+        let source = "const a = 1; // keep\n/** Unused. */\nexport interface Old {\n  id: string;\n  name: string;\n}\n\nexport const b = 2;\n";
+
+        let nodes = analyzer.find_positions(source).unwrap();
+
+        let mut without = source.to_string();
+        without.replace_range(nodes[0].children[0].removal.clone(), "");
+        assert_eq!(
+            without,
+            "const a = 1; // keep\n/** Unused. */\nexport interface Old {\n  name: string;\n}\n\nexport const b = 2;\n"
+        );
+        let mut without = source.to_string();
+        without.replace_range(nodes[0].removal.clone(), "");
+        assert_eq!(without, "const a = 1; // keep\nexport const b = 2;\n");
+    }
+
+    fn remove_declarators(source: &str, names: &[&str]) -> String {
+        let target = NodeTarget {
+            node_type: "variable_declarator".to_string(),
+            ref_type: Some("identifier".to_string()),
+            children: vec![],
+        };
+        let mut analyzer = TreeAnalyzer::new(Grammar::TypeScript, vec![target], None).unwrap();
+        let nodes = analyzer.find_positions(source).unwrap();
+        let mut without = source.to_string();
+        for node in nodes.iter().rev() {
+            if names.contains(&node.name.as_str()) {
+                without.replace_range(node.removal.clone(), "");
+            }
+        }
+        without
+    }
+
+    #[test]
+    fn removal_of_single_declarator_covers_its_declaration() {
+        let source = "export const helper = 1;\nconst used = 2;\nconsole.log(used);\n";
+        assert_eq!(
+            remove_declarators(source, &["helper"]),
+            "const used = 2;\nconsole.log(used);\n"
+        );
+        assert_eq!(
+            remove_declarators("let a = 1;\nvar b = 2;\n", &["b"]),
+            "let a = 1;\n"
+        );
+    }
+
+    #[test]
+    fn removal_of_one_of_several_declarators_keeps_the_others() {
+        let source = "export const helper = 1, other = 2, last = 3;\nexport default class {}\n";
+        assert_eq!(
+            remove_declarators(source, &["helper"]),
+            "export const other = 2, last = 3;\nexport default class {}\n"
+        );
+        assert_eq!(
+            remove_declarators(source, &["other"]),
+            "export const helper = 1, last = 3;\nexport default class {}\n"
+        );
+        assert_eq!(
+            remove_declarators(source, &["last"]),
+            "export const helper = 1, other = 2;\nexport default class {}\n"
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Scriptable stand-in for a language server, used by the integration tests.
+//!
+//! It answers `initialize`, `textDocument/references`, `textDocument/rename` and
+//! `shutdown` over stdio.
+//! Reference results come from a fixture passed as `--fixture <path>`:
+//!
+//! ```json
+//! { "references": { "Foo": 2, "Bar": [{ "file": "bar.spec.ts", "line": 3, "character": 4 }] } }
+//! ```
+//!
+//! A number produces that many synthetic usages in `usages.ts` under the workspace root,
+//! a list produces the given locations (paths relative to the workspace root). The
+//! declaration itself is always returned first, as real servers do. A rename replaces
+//! the declaration and every one of those locations with the new name.
+//!
+//! With `--tcp <addr>` or `--unix <path>` the server listens on a socket instead, prints
+//! the bound address on stdout and serves one client connection after another.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, Default, Deserialize)]
+struct Fixture {
+    #[serde(default)]
+    references: HashMap<String, FixtureReferences>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FixtureReferences {
+    Count(usize),
+    Locations(Vec<FixtureLocation>),
+}
+
+#[derive(Debug, Deserialize)]
+struct FixtureLocation {
+    file: PathBuf,
+    line: u32,
+    #[serde(default)]
+    character: u32,
+}
+
+struct FakeServer {
+    fixture: Fixture,
+    root: Option<Url>,
+    documents: HashMap<String, String>,
+}
+
+enum Listen {
+    Stdio,
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+struct Args {
+    fixture: Option<PathBuf>,
+    listen: Listen,
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let fixture = match args.fixture {
+        Some(path) => {
+            let raw = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+            serde_json::from_str(&raw).context("Invalid fixture JSON")?
+        }
+        None => Fixture::default(),
+    };
+
+    let mut server = FakeServer {
+        fixture,
+        root: None,
+        documents: HashMap::new(),
+    };
+
+    match args.listen {
+        Listen::Stdio => {
+            let stdin = io::stdin();
+            server.serve(&mut BufReader::new(stdin.lock()), &mut io::stdout().lock())
+        }
+        Listen::Tcp(address) => {
+            let listener = TcpListener::bind(&address)?;
+            announce(&listener.local_addr()?.to_string())?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                server.serve(&mut BufReader::new(stream.try_clone()?), &mut &stream)?;
+            }
+            Ok(())
+        }
+        #[cfg(unix)]
+        Listen::Unix(path) => {
+            let listener = UnixListener::bind(&path)?;
+            announce(&path.display().to_string())?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                server.serve(&mut BufReader::new(stream.try_clone()?), &mut &stream)?;
+            }
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        Listen::Unix(_) => bail!("Unix sockets are not supported on this platform"),
+    }
+}
+
+fn parse_args() -> Result<Args> {
+    let mut parsed = Args {
+        fixture: None,
+        listen: Listen::Stdio,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{arg} expects a value"));
+        match arg.as_str() {
+            "--fixture" => parsed.fixture = Some(PathBuf::from(value()?)),
+            "--tcp" => parsed.listen = Listen::Tcp(value()?),
+            "--unix" => parsed.listen = Listen::Unix(PathBuf::from(value()?)),
+            _ => {}
+        }
+    }
+    Ok(parsed)
+}
+
+fn announce(address: &str) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{address}")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+impl FakeServer {
+    /// Serves a single client until it sends `exit` or closes the stream.
+    fn serve(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> Result<()> {
+        while let Some(message) = read_frame(reader)? {
+            let method = message.get("method").and_then(Value::as_str);
+            let id = message.get("id").cloned();
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+            match (method, id) {
+                (Some("exit"), _) => break,
+                (Some(method), Some(id)) => {
+                    let result = self.handle_request(method, params)?;
+                    write_frame(
+                        writer,
+                        &json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    )?;
+                }
+                (Some(method), None) => self.handle_notification(method, params),
+                (None, _) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, method: &str, params: Value) -> Result<Value> {
+        match method {
+            "initialize" => {
+                self.root = params
+                    .pointer("/workspaceFolders/0/uri")
+                    .and_then(Value::as_str)
+                    .and_then(|uri| Url::parse(uri).ok());
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "referencesProvider": true,
+                        "renameProvider": true
+                    },
+                    "serverInfo": { "name": "sca-fake-lsp" }
+                }))
+            }
+            "textDocument/references" => self.references(&params),
+            "textDocument/rename" => self.rename(&params),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: Value) {
+        match method {
+            "textDocument/didOpen" => {
+                let uri = params.pointer("/textDocument/uri").and_then(Value::as_str);
+                let text = params.pointer("/textDocument/text").and_then(Value::as_str);
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params.pointer("/textDocument/uri").and_then(Value::as_str) {
+                    self.documents.remove(uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn references(&self, params: &Value) -> Result<Value> {
+        let uri = params
+            .pointer("/textDocument/uri")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("references request without uri"))?;
+        let line = params
+            .pointer("/position/line")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+        let character = params
+            .pointer("/position/character")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
+        let name = self
+            .documents
+            .get(uri)
+            .map(|text| identifier_at(text, line, character))
+            .unwrap_or_default();
+
+        let mut locations = vec![location(uri, line, character, name.len() as u32)];
+        match self.fixture.references.get(&name) {
+            Some(FixtureReferences::Count(count)) => {
+                let usages = self.resolve("usages.ts")?;
+                for idx in 0..*count {
+                    locations.push(location(usages.as_str(), idx as u32, 0, name.len() as u32));
+                }
+            }
+            Some(FixtureReferences::Locations(list)) => {
+                for item in list {
+                    let file = self.resolve(&item.file.to_string_lossy())?;
+                    locations.push(location(
+                        file.as_str(),
+                        item.line,
+                        item.character,
+                        name.len() as u32,
+                    ));
+                }
+            }
+            None => {}
+        }
+
+        Ok(Value::Array(locations))
+    }
+
+    fn rename(&self, params: &Value) -> Result<Value> {
+        let new_name = params
+            .get("newName")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("rename request without newName"))?;
+        let mut changes = serde_json::Map::new();
+        if let Value::Array(locations) = self.references(params)? {
+            for location in locations {
+                let (Some(uri), Some(range)) = (location["uri"].as_str(), location.get("range"))
+                else {
+                    continue;
+                };
+                let edits = changes
+                    .entry(uri.to_string())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                if let Value::Array(edits) = edits {
+                    edits.push(json!({ "range": range, "newText": new_name }));
+                }
+            }
+        }
+        Ok(json!({ "changes": changes }))
+    }
+
+    fn resolve(&self, relative: &str) -> Result<Url> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow!("initialize did not provide a workspace folder"))?;
+        Ok(root.join(relative)?)
+    }
+}
+
+fn location(uri: &str, line: u32, character: u32, len: u32) -> Value {
+    json!({
+        "uri": uri,
+        "range": {
+            "start": { "line": line, "character": character },
+            "end": { "line": line, "character": character + len }
+        }
+    })
+}
+
+fn identifier_at(text: &str, line: u32, character: u32) -> String {
+    text.lines()
+        .nth(line as usize)
+        .map(|row| {
+            row.chars()
+                .skip(character as usize)
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '$')
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn read_frame(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(value) = trimmed.strip_prefix("Content-Length:") {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let Some(length) = content_length else {
+        bail!("Missing Content-Length header");
+    };
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer)?;
+    Ok(Some(serde_json::from_slice(&buffer)?))
+}
+
+fn write_frame(writer: &mut impl Write, value: &Value) -> Result<()> {
+    let body = serde_json::to_vec(value)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(&body)?;
+    writer.flush()?;
+    Ok(())
+}
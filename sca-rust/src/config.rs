@@ -1,13 +1,15 @@
-use crate::model::NodeTarget;
+use crate::model::{NodeTarget, Severity};
 use anyhow::{bail, Context, Result};
 use encoding_rs::{Encoding, UTF_8};
 use globset::{Glob, GlobSet, GlobSetBuilder};
 use regex::Regex;
 use serde::Deserialize;
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Tree-sitter grammar used to parse the analysed files.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Grammar {
     TypeScript,
@@ -22,6 +24,136 @@ impl Grammar {
     }
 }
 
+/// Where the LSP client finds the language server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LspTransport {
+    /// Spawn `lspExecutable` and talk over its stdin/stdout.
+    Stdio,
+    /// Connect to an already running server listening on a TCP address.
+    Tcp(String),
+    /// Connect to an already running server listening on a Unix domain socket.
+    Unix(PathBuf),
+}
+
+/// How the LSP client talks to the server: live, live with a JSON-RPC log, or from a log.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LspSession {
+    #[default]
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// Backend answering which locations reference an entity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReferenceBackend {
+    /// `textDocument/references` of the configured language server.
+    #[default]
+    Lsp,
+    /// A SCIP index or LSIF dump produced ahead of time, without any language server.
+    Index(PathBuf),
+}
+
+/// Rules deciding which reference locations count as real usages.
+#[derive(Debug, Clone)]
+pub struct ReferenceFilter {
+    /// Drop references located inside the referenced node itself (recursion, self-typing).
+    pub ignore_self_references: bool,
+    /// Drop references from files matching these globs (tests, stories, ...).
+    pub exclude_glob: GlobSet,
+}
+
+/// Flags entities with only a handful of usages as inlining candidates.
+#[derive(Debug, Clone)]
+pub struct LowUsageRule {
+    pub max_references: usize,
+    pub severity: Severity,
+    /// Node types the rule applies to; empty means every reference node.
+    pub node_types: Vec<String>,
+}
+
+impl LowUsageRule {
+    pub fn matches(&self, node_type: &str, references: usize) -> bool {
+        (1..=self.max_references).contains(&references)
+            && (self.node_types.is_empty() || self.node_types.iter().any(|t| t == node_type))
+    }
+}
+
+/// Controls the "useless prefix" check of child names against their parent name.
+#[derive(Debug, Clone)]
+pub struct PrefixRule {
+    pub enabled: bool,
+    pub case_sensitive: bool,
+    /// Parents with shorter names are never checked.
+    pub min_length: usize,
+    /// Also flag names ending with the parent name.
+    pub detect_suffix: bool,
+}
+
+/// Settings of the naming rules, from the `naming` section.
+#[derive(Debug, Clone)]
+pub struct NamingRules {
+    pub prefix: PrefixRule,
+    /// Flag `IUser`-style interface names.
+    pub hungarian_interfaces: bool,
+    /// Required name pattern per node type.
+    pub conventions: HashMap<String, Regex>,
+    pub max_identifier_length: Option<usize>,
+}
+
+/// Upper bounds of the structural size rules; unset limits are not checked.
+#[derive(Debug, Clone, Default)]
+pub struct SizeLimits {
+    pub max_properties: Option<usize>,
+    pub max_methods: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub max_depth: Option<usize>,
+    /// Node types the limits apply to; empty means every reference node.
+    pub node_types: Vec<String>,
+}
+
+impl SizeLimits {
+    pub fn applies_to(&self, node_type: &str) -> bool {
+        self.node_types.is_empty() || self.node_types.iter().any(|t| t == node_type)
+    }
+}
+
+/// Reports function parameters and destructured parameter fields unused in their function.
+#[derive(Debug, Clone)]
+pub struct ParameterRule {
+    /// Skip `_`-prefixed names, the usual marker of intentionally unused parameters.
+    pub ignore_underscore: bool,
+}
+
+/// Reports top-level entities declared under the same name in several files.
+#[derive(Debug, Clone)]
+pub struct DuplicateNameRule {
+    /// Node types compared with each other; empty means every reference node.
+    pub node_types: Vec<String>,
+    /// Names allowed to repeat across files.
+    pub allow: HashSet<String>,
+    /// Only compare exported entities.
+    pub exported_only: bool,
+}
+
+/// Settings of the rule reporting entities no entry point reaches.
+#[derive(Debug, Clone)]
+pub struct ReachabilityRule {
+    /// Files run on their own, such as `main.ts`, route modules or public API barrels,
+    /// matched relative to the project root.
+    pub entry_points: GlobSet,
+}
+
+/// Per-rule overrides from the `rules` section; unset fields keep the rule defaults.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSettings {
+    pub enabled: Option<bool>,
+    pub severity: Option<Severity>,
+    /// Maximum number of findings before the run fails.
+    pub threshold: Option<usize>,
+}
+
+/// Validated configuration with paths resolved against the configuration file.
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub show_passed: bool,
@@ -31,17 +163,29 @@ pub struct AppConfig {
     pub analyze_directory: PathBuf,
     pub lsp_executable: String,
     pub lsp_args: Vec<String>,
+    pub lsp_transport: LspTransport,
+    pub reference_backend: ReferenceBackend,
     pub file_matching_glob: GlobSet,
     pub file_exclude_glob: GlobSet,
     pub content_pattern: Option<Regex>,
     pub lsp_capabilities: Option<Value>,
     pub initialization_options: Value,
     pub reference_nodes: Vec<NodeTarget>,
+    pub reference_filter: ReferenceFilter,
+    pub test_file_glob: GlobSet,
+    pub low_usage: Option<LowUsageRule>,
+    pub naming: NamingRules,
+    pub size: SizeLimits,
+    pub duplicates: Option<DuplicateNameRule>,
+    pub parameters: Option<ParameterRule>,
+    pub reachability: Option<ReachabilityRule>,
+    pub rules: HashMap<String, RuleSettings>,
     pub lsp_version: String,
     pub lsp_name: String,
     pub grammar: Grammar,
     pub encoding: &'static Encoding,
     pub encoding_label: String,
+    pub lsp_session: LspSession,
 }
 
 impl AppConfig {
@@ -60,14 +204,20 @@ impl AppConfig {
         Ok(content.into_owned())
     }
 
+    /// One-line description for logs.
     pub fn summary(&self) -> String {
+        let lsp = match (&self.reference_backend, &self.lsp_transport) {
+            (ReferenceBackend::Index(path), _) => format!("index {}", path.display()),
+            (_, LspTransport::Stdio) => format!("{} {:#?}", self.lsp_executable, self.lsp_args),
+            (_, LspTransport::Tcp(address)) => format!("tcp://{address}"),
+            (_, LspTransport::Unix(path)) => format!("unix://{}", path.display()),
+        };
         format!(
-            "root={}, analyze={}, grammar={:?}, lsp={} {:#?}",
+            "root={}, analyze={}, grammar={:?}, lsp={}",
             self.project_root_path.display(),
             self.analyze_directory.display(),
             self.grammar,
-            self.lsp_executable,
-            self.lsp_args
+            lsp
         )
     }
 }
@@ -98,6 +248,129 @@ impl RawNodeTarget {
     }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawReferenceFilters {
+    #[serde(default)]
+    ignore_self_references: bool,
+    #[serde(default)]
+    exclude_globs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLowUsageRule {
+    #[serde(default = "default_low_usage_max_references")]
+    max_references: usize,
+    #[serde(default = "default_low_usage_severity")]
+    severity: Severity,
+    #[serde(default)]
+    node_types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPrefixRule {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default = "default_prefix_min_length")]
+    min_length: usize,
+    #[serde(default)]
+    detect_suffix: bool,
+}
+
+impl Default for RawPrefixRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            case_sensitive: false,
+            min_length: default_prefix_min_length(),
+            detect_suffix: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawNamingRules {
+    #[serde(default)]
+    prefix: RawPrefixRule,
+    #[serde(default)]
+    hungarian_interfaces: bool,
+    #[serde(default)]
+    conventions: HashMap<String, String>,
+    #[serde(default)]
+    max_identifier_length: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSizeLimits {
+    #[serde(default)]
+    max_properties: Option<usize>,
+    #[serde(default)]
+    max_methods: Option<usize>,
+    #[serde(default)]
+    max_lines: Option<usize>,
+    #[serde(default)]
+    max_depth: Option<usize>,
+    #[serde(default)]
+    node_types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawDuplicateNameRule {
+    #[serde(default)]
+    node_types: Vec<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default = "default_true")]
+    exported_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawParameterRule {
+    #[serde(default = "default_true")]
+    ignore_underscore: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawReachabilityRule {
+    entry_points: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRuleSettings {
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    severity: Option<Severity>,
+    #[serde(default)]
+    threshold: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawTransport {
+    Stdio,
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawReferenceProvider {
+    #[default]
+    Lsp,
+    Index(PathBuf),
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct RawConfig {
@@ -109,9 +382,14 @@ struct RawConfig {
     #[serde(default)]
     show_progress: bool,
     analyze_directory: PathBuf,
+    #[serde(default)]
     lsp_executable: String,
     #[serde(default)]
     lsp_args: Vec<String>,
+    #[serde(default)]
+    lsp_transport: Option<RawTransport>,
+    #[serde(default)]
+    reference_provider: RawReferenceProvider,
     #[serde(default = "default_file_glob")]
     file_matching_regexp: String,
     #[serde(default)]
@@ -123,6 +401,24 @@ struct RawConfig {
     #[serde(default = "default_initialization_options")]
     initialization_options: Value,
     reference_nodes: Vec<RawNodeTarget>,
+    #[serde(default)]
+    reference_filters: RawReferenceFilters,
+    #[serde(default)]
+    test_file_globs: Vec<String>,
+    #[serde(default)]
+    low_usage: Option<RawLowUsageRule>,
+    #[serde(default)]
+    naming: RawNamingRules,
+    #[serde(default)]
+    size: RawSizeLimits,
+    #[serde(default)]
+    duplicates: Option<RawDuplicateNameRule>,
+    #[serde(default)]
+    parameters: Option<RawParameterRule>,
+    #[serde(default)]
+    reachability: Option<RawReachabilityRule>,
+    #[serde(default)]
+    rules: HashMap<String, RawRuleSettings>,
     #[serde(default = "default_lsp_version")]
     lsp_version: String,
     lsp_name: String,
@@ -149,10 +445,27 @@ fn default_initialization_options() -> Value {
     })
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_prefix_min_length() -> usize {
+    1
+}
+
+fn default_low_usage_max_references() -> usize {
+    1
+}
+
+fn default_low_usage_severity() -> Severity {
+    Severity::Warning
+}
+
 fn default_lsp_version() -> String {
     "0.0.0".to_string()
 }
 
+/// Reads and validates a JSON configuration; `threshold_override` replaces `threshold`.
 pub fn load_config(path: &Path, threshold_override: Option<usize>) -> Result<AppConfig> {
     let raw_bytes = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
     let RawConfig {
@@ -163,12 +476,23 @@ pub fn load_config(path: &Path, threshold_override: Option<usize>) -> Result<App
         analyze_directory,
         lsp_executable,
         lsp_args,
+        lsp_transport,
+        reference_provider,
         file_matching_regexp,
         file_exclude_regexps,
         content_matching_regexp,
         lsp_capabilities,
         initialization_options,
         reference_nodes,
+        reference_filters,
+        test_file_globs,
+        low_usage,
+        naming,
+        size,
+        duplicates,
+        parameters,
+        reachability,
+        rules,
         lsp_version,
         lsp_name,
         grammar,
@@ -184,6 +508,22 @@ pub fn load_config(path: &Path, threshold_override: Option<usize>) -> Result<App
     let project_root_path = absolutize(&config_dir, project_root_path);
     let analyze_directory = absolutize(&config_dir, analyze_directory);
 
+    let reference_backend = match reference_provider {
+        RawReferenceProvider::Lsp => ReferenceBackend::Lsp,
+        RawReferenceProvider::Index(path) => ReferenceBackend::Index(absolutize(&config_dir, path)),
+    };
+    let lsp_transport = match lsp_transport.unwrap_or(RawTransport::Stdio) {
+        RawTransport::Stdio => {
+            // Only the language server backend spawns it.
+            if reference_backend == ReferenceBackend::Lsp && lsp_executable.trim().is_empty() {
+                bail!("lspExecutable is required when lspTransport is stdio");
+            }
+            LspTransport::Stdio
+        }
+        RawTransport::Tcp(address) => LspTransport::Tcp(address),
+        RawTransport::Unix(path) => LspTransport::Unix(absolutize(&config_dir, path)),
+    };
+
     let grammar = Grammar::from_str(&grammar)?;
     let encoding_label = encoding;
     let encoding = resolve_encoding(&encoding_label)?;
@@ -202,6 +542,54 @@ pub fn load_config(path: &Path, threshold_override: Option<usize>) -> Result<App
         .into_iter()
         .filter_map(|node| node.into_target())
         .collect();
+    let reference_filter = ReferenceFilter {
+        ignore_self_references: reference_filters.ignore_self_references,
+        exclude_glob: compile_glob(&reference_filters.exclude_globs, None)
+            .context("Invalid referenceFilters.excludeGlobs")?,
+    };
+    let test_file_glob = compile_glob(&test_file_globs, None).context("Invalid testFileGlobs")?;
+    let low_usage = low_usage.map(|rule| LowUsageRule {
+        max_references: rule.max_references,
+        severity: rule.severity,
+        node_types: rule.node_types,
+    });
+    let naming = compile_naming(naming)?;
+    let size = SizeLimits {
+        max_properties: size.max_properties,
+        max_methods: size.max_methods,
+        max_lines: size.max_lines,
+        max_depth: size.max_depth,
+        node_types: size.node_types,
+    };
+    let duplicates = duplicates.map(|rule| DuplicateNameRule {
+        node_types: rule.node_types,
+        allow: rule.allow.into_iter().collect(),
+        exported_only: rule.exported_only,
+    });
+    let parameters = parameters.map(|rule| ParameterRule {
+        ignore_underscore: rule.ignore_underscore,
+    });
+    let reachability = reachability
+        .map(|rule| {
+            if rule.entry_points.is_empty() {
+                bail!("reachability.entryPoints must list at least one pattern");
+            }
+            let entry_points = compile_glob(&rule.entry_points, None)
+                .context("Invalid reachability.entryPoints")?;
+            Ok(ReachabilityRule { entry_points })
+        })
+        .transpose()?;
+    let rules = rules
+        .into_iter()
+        .map(|(id, settings)| {
+            let settings = RuleSettings {
+                enabled: settings.enabled,
+                severity: settings.severity,
+                threshold: settings.threshold,
+            };
+            (id, settings)
+        })
+        .collect();
 
     Ok(AppConfig {
         show_passed,
@@ -211,17 +599,53 @@ pub fn load_config(path: &Path, threshold_override: Option<usize>) -> Result<App
         analyze_directory,
         lsp_executable,
         lsp_args,
+        lsp_transport,
+        reference_backend,
         file_matching_glob,
         file_exclude_glob,
         content_pattern,
         lsp_capabilities,
         initialization_options,
         reference_nodes,
+        reference_filter,
+        test_file_glob,
+        low_usage,
+        naming,
+        size,
+        duplicates,
+        parameters,
+        reachability,
+        rules,
         lsp_version,
         lsp_name,
         grammar,
         encoding,
         encoding_label,
+        lsp_session: LspSession::Live,
+    })
+}
+
+fn compile_naming(raw: RawNamingRules) -> Result<NamingRules> {
+    let conventions = raw
+        .conventions
+        .into_iter()
+        .map(|(node_type, pattern)| {
+            let regex = Regex::new(&pattern)
+                .with_context(|| format!("Invalid naming convention for {node_type}"))?;
+            Ok((node_type, regex))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(NamingRules {
+        prefix: PrefixRule {
+            enabled: raw.prefix.enabled,
+            case_sensitive: raw.prefix.case_sensitive,
+            min_length: raw.prefix.min_length,
+            detect_suffix: raw.prefix.detect_suffix,
+        },
+        hungarian_interfaces: raw.hungarian_interfaces,
+        conventions,
+        max_identifier_length: raw.max_identifier_length,
     })
 }
 
@@ -0,0 +1,403 @@
+use anyhow::{anyhow, bail, Context, Result};
+use sca::analyzer::files::collect_files;
+use sca::analyzer::{Analyzer, ProjectAnalysis};
+use sca::config::load_config;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Analysis requested from the daemon by a CLI run.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonRequest {
+    pub config_file: PathBuf,
+    /// Same as `--files-from`; every matching file when absent.
+    pub files: Option<Vec<PathBuf>>,
+}
+
+impl DaemonRequest {
+    pub fn new(config_file: &Path, files: Option<&HashSet<PathBuf>>) -> Result<Self> {
+        let config_file = fs::canonicalize(config_file)
+            .with_context(|| format!("Failed to resolve {}", config_file.display()))?;
+        Ok(Self {
+            config_file,
+            files: files.map(|files| files.iter().cloned().collect()),
+        })
+    }
+}
+
+/// Lines the daemon sends on a connection: a greeting, heartbeats while the request is
+/// queued or analysed, then the result.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum DaemonResponse {
+    Hello { version: String, busy: bool },
+    Working,
+    Analysis(ProjectAnalysis),
+    Error(String),
+}
+
+/// Clients only talk to a daemon of the same build, which speaks the same protocol.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Time a client gets to send its request once connected.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Interval of the heartbeats telling a client its request is still in progress.
+const HEARTBEAT: Duration = Duration::from_secs(5);
+
+/// A request waiting for the analysis loop, with where to send its response.
+type Job = (DaemonRequest, oneshot::Sender<DaemonResponse>);
+
+/// An analyzer with its language server, kept for later runs of the same config.
+struct WarmAnalyzer {
+    /// Raw configuration it was started with; any edit starts a fresh analyzer.
+    config_source: Vec<u8>,
+    analyzer: Analyzer,
+}
+
+/// Per-user socket so several users of one machine don't share a daemon: in
+/// `$XDG_RUNTIME_DIR` when set, otherwise in a private directory of the temporary one.
+pub fn default_socket() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("sca-daemon.sock"),
+        _ => fallback_dir().join("daemon.sock"),
+    }
+}
+
+fn fallback_dir() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+    std::env::temp_dir().join(format!("sca-{user}"))
+}
+
+/// Sends `request` to a running daemon; `None` when there is none, it failed or it went
+/// silent for `timeout`, so the caller analyses in process instead.
+#[cfg(unix)]
+pub async fn delegate(
+    socket: &Path,
+    request: &DaemonRequest,
+    timeout: Duration,
+) -> Option<ProjectAnalysis> {
+    let stream = match tokio::net::UnixStream::connect(socket).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            tracing::debug!("No daemon at {}: {err}", socket.display());
+            return None;
+        }
+    };
+    match exchange(stream, request, timeout).await {
+        Ok(analysis) => Some(analysis),
+        Err(err) => {
+            tracing::warn!("Daemon failed, analysing in process: {err:#}");
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn delegate(
+    _socket: &Path,
+    _request: &DaemonRequest,
+    _timeout: Duration,
+) -> Option<ProjectAnalysis> {
+    None
+}
+
+#[cfg(unix)]
+async fn exchange(
+    stream: tokio::net::UnixStream,
+    request: &DaemonRequest,
+    timeout: Duration,
+) -> Result<ProjectAnalysis> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    // Requests name files to read, so only hand them to a daemon of the same user.
+    if !same_user(&stream)? {
+        bail!("Daemon runs as another user");
+    }
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    match next_response(&mut lines, timeout).await? {
+        DaemonResponse::Hello { version, .. } if version != VERSION => {
+            bail!("Daemon runs sca {version}, this is {VERSION}")
+        }
+        DaemonResponse::Hello { busy, .. } => {
+            if busy {
+                tracing::info!("Daemon is busy, waiting for it");
+            }
+        }
+        _ => bail!("Daemon did not greet"),
+    }
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+
+    // Heartbeats keep the wait going however long a cold analysis or the queue takes.
+    loop {
+        match next_response(&mut lines, timeout).await? {
+            DaemonResponse::Working => {}
+            DaemonResponse::Analysis(analysis) => return Ok(analysis),
+            DaemonResponse::Error(message) => bail!(message),
+            DaemonResponse::Hello { .. } => bail!("Daemon greeted twice"),
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn next_response<R>(
+    lines: &mut tokio::io::Lines<R>,
+    timeout: Duration,
+) -> Result<DaemonResponse>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    let line = tokio::time::timeout(timeout, lines.next_line())
+        .await
+        .map_err(|_| anyhow!("No answer within {}s", timeout.as_secs()))??
+        .context("Daemon closed the connection")?;
+    serde_json::from_str(&line).context("Invalid daemon response")
+}
+
+#[cfg(unix)]
+fn same_user(stream: &tokio::net::UnixStream) -> Result<bool> {
+    // SAFETY: `geteuid` has no preconditions and cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    Ok(stream.peer_cred()?.uid() == uid)
+}
+
+/// Creates the directory holding the socket, readable by its owner only, and refuses the
+/// default one when somebody else created it first.
+#[cfg(unix)]
+fn prepare_socket_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    if dir == fallback_dir() {
+        let metadata = fs::metadata(dir)?;
+        // SAFETY: `geteuid` has no preconditions and cannot fail.
+        if metadata.uid() != unsafe { libc::geteuid() } {
+            bail!("{} belongs to another user", dir.display());
+        }
+        if metadata.permissions().mode() & 0o077 != 0 {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    Ok(())
+}
+
+/// Serves analysis requests on `socket` until interrupted.
+///
+/// Connections are handled concurrently, so a client that never sends its request blocks
+/// nobody, while the analyses themselves run one at a time.
+#[cfg(unix)]
+pub async fn serve(socket: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    if UnixStream::connect(socket).await.is_ok() {
+        bail!("A daemon is already listening on {}", socket.display());
+    }
+    if let Some(dir) = socket.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        prepare_socket_dir(dir)?;
+    }
+    // Left behind by a daemon that did not shut down cleanly.
+    let _ = fs::remove_file(socket);
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    println!("Listening on {}", socket.display());
+
+    let (jobs, mut queue) = mpsc::unbounded_channel();
+    let pending = Arc::new(AtomicUsize::new(0));
+    let acceptor = tokio::spawn(accept(listener, jobs, pending.clone()));
+
+    let mut analyzers: HashMap<PathBuf, WarmAnalyzer> = HashMap::new();
+    // Created once, so an interrupt during an analysis is not lost.
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let (request, reply): Job = tokio::select! {
+            job = queue.recv() => match job {
+                Some(job) => job,
+                None => break,
+            },
+            _ = &mut interrupted => break,
+        };
+        let response = match analyze(&mut analyzers, &request).await {
+            Ok(analysis) => {
+                println!(
+                    "Analyzed {} files for {}",
+                    analysis.files,
+                    request.config_file.display()
+                );
+                DaemonResponse::Analysis(analysis)
+            }
+            Err(err) => {
+                // The language server may be gone; start over on the next request.
+                if let Some(mut warm) = analyzers.remove(&request.config_file) {
+                    let _ = warm.analyzer.shutdown().await;
+                }
+                DaemonResponse::Error(format!("{err:#}"))
+            }
+        };
+        pending.fetch_sub(1, Ordering::SeqCst);
+        // The client may have given up in the meantime.
+        let _ = reply.send(response);
+    }
+
+    acceptor.abort();
+    for warm in analyzers.values_mut() {
+        let _ = warm.analyzer.shutdown().await;
+    }
+    let _ = fs::remove_file(socket);
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn accept(
+    listener: tokio::net::UnixListener,
+    jobs: mpsc::UnboundedSender<Job>,
+    pending: Arc<AtomicUsize>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                tracing::warn!("Daemon stopped accepting connections: {err}");
+                return;
+            }
+        };
+        let jobs = jobs.clone();
+        let pending = pending.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve_connection(stream, jobs, pending).await {
+                tracing::warn!("Daemon request failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Greets the client, reads its request and passes it to the analysis loop, sending
+/// heartbeats until the response is ready.
+#[cfg(unix)]
+async fn serve_connection(
+    stream: tokio::net::UnixStream,
+    jobs: mpsc::UnboundedSender<Job>,
+    pending: Arc<AtomicUsize>,
+) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    if !same_user(&stream)? {
+        bail!("Ignoring daemon client of another user");
+    }
+    let (reader, mut writer) = stream.into_split();
+    let busy = pending.load(Ordering::SeqCst) > 0;
+    send(
+        &mut writer,
+        &DaemonResponse::Hello {
+            version: VERSION.to_string(),
+            busy,
+        },
+    )
+    .await?;
+
+    let mut line = String::new();
+    tokio::time::timeout(REQUEST_TIMEOUT, BufReader::new(reader).read_line(&mut line))
+        .await
+        .map_err(|_| anyhow!("No request within {}s", REQUEST_TIMEOUT.as_secs()))??;
+    let request = match serde_json::from_str::<DaemonRequest>(&line) {
+        Ok(request) => request,
+        Err(err) => {
+            return send(
+                &mut writer,
+                &DaemonResponse::Error(format!("Invalid request: {err}")),
+            )
+            .await;
+        }
+    };
+
+    let (reply, response) = oneshot::channel();
+    pending.fetch_add(1, Ordering::SeqCst);
+    if jobs.send((request, reply)).is_err() {
+        pending.fetch_sub(1, Ordering::SeqCst);
+        bail!("Daemon is shutting down");
+    }
+    tokio::pin!(response);
+    let mut heartbeat =
+        tokio::time::interval_at(tokio::time::Instant::now() + HEARTBEAT, HEARTBEAT);
+    loop {
+        tokio::select! {
+            response = &mut response => {
+                let response = response.context("Daemon dropped the request")?;
+                return send(&mut writer, &response).await;
+            }
+            _ = heartbeat.tick() => send(&mut writer, &DaemonResponse::Working).await?,
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn send(
+    writer: &mut tokio::net::unix::OwnedWriteHalf,
+    response: &DaemonResponse,
+) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut payload = serde_json::to_vec(response)?;
+    payload.push(b'\n');
+    writer.write_all(&payload).await?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn serve(_socket: &Path) -> Result<()> {
+    bail!("The daemon requires Unix sockets")
+}
+
+async fn analyze(
+    analyzers: &mut HashMap<PathBuf, WarmAnalyzer>,
+    request: &DaemonRequest,
+) -> Result<ProjectAnalysis> {
+    let config_source = fs::read(&request.config_file)
+        .with_context(|| format!("Failed to read {}", request.config_file.display()))?;
+    let config = load_config(&request.config_file, None)?;
+
+    let stale = analyzers
+        .get(&request.config_file)
+        .is_some_and(|warm| warm.config_source != config_source);
+    if stale {
+        if let Some(mut warm) = analyzers.remove(&request.config_file) {
+            warm.analyzer.shutdown().await?;
+        }
+    }
+    if !analyzers.contains_key(&request.config_file) {
+        tracing::info!("Starting analyzer for {}", request.config_file.display());
+        let analyzer = Analyzer::new(&config).await?;
+        analyzers.insert(
+            request.config_file.clone(),
+            WarmAnalyzer {
+                config_source,
+                analyzer,
+            },
+        );
+    }
+    let warm = analyzers
+        .get_mut(&request.config_file)
+        .expect("analyzer was just started");
+
+    let only: Option<HashSet<PathBuf>> = request
+        .files
+        .as_ref()
+        .map(|files| files.iter().cloned().collect());
+    let files = collect_files(&config, only.as_ref())?;
+    warm.analyzer.analyze_project(&files, |_| {}).await
+}
@@ -0,0 +1,48 @@
+//! Smells Code Analyzer as a library.
+//!
+//! The `sca` binary is a thin layer over this crate: it loads a configuration, collects
+//! the files to analyse, runs them through an [`Analyzer`] and renders the findings.
+//! Tools embedding the analyzer get the same results as typed values instead of the
+//! emoji-styled console report.
+//!
+//! ```no_run
+//! use sca::{collect_files, load_config, Analyzer};
+//! use std::path::Path;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = load_config(Path::new("sca.json"), None)?;
+//! let mut analyzer = Analyzer::new(&config).await?;
+//! let files = collect_files(&config, None)?;
+//! let analysis = analyzer.analyze_project(&files, |_| {}).await?;
+//! for summary in analyzer.rules().summarize(&analysis.nodes) {
+//!     println!("{}: {}", summary.id, summary.count);
+//! }
+//! analyzer.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Analyzer::new`] starts the configured language server; call
+//! [`Analyzer::shutdown`] once done so it exits cleanly. [`Analyzer::with_provider`]
+//! takes any [`ReferenceProvider`] instead, e.g. an in-memory one in tests. The
+//! [`lsp_types`], [`tree_sitter`] and [`url`] crates its signatures use are re-exported.
+
+pub mod analyzer;
+/// Loading and validation of the JSON configuration.
+pub mod config;
+/// Entities, findings and reference locations produced by the analysis.
+pub mod model;
+mod sanitize;
+
+pub use analyzer::files::collect_files;
+pub use analyzer::provider::ReferenceProvider;
+pub use analyzer::report::{build_report, reasons};
+pub use analyzer::BoxFuture;
+pub use analyzer::{AnalysisEvent, Analyzer, ProjectAnalysis};
+pub use config::{load_config, AppConfig};
+pub use model::{Finding, FullNodeInfo, ReferenceLocation, Severity};
+
+// Crates whose types appear in the public API, notably in [`ReferenceProvider`].
+pub use lsp_types;
+pub use tree_sitter;
+pub use url;
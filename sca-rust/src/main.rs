@@ -1,56 +1,145 @@
-mod analyzer;
-mod config;
-mod model;
-mod sanitize;
-
-use crate::analyzer::report::build_report;
-use crate::analyzer::{count_dead_entities, Analyzer};
-use crate::config::load_config;
-use anyhow::{Context, Result};
-use clap::Parser;
+mod daemon;
+mod server;
+mod watch;
+
+use crate::daemon::DaemonRequest;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use sca::analyzer::fix::{render_patch, FileFix, FixPlan};
+use sca::analyzer::rules::{count_findings, RuleSet, RuleSummary, DEAD_CODE};
+use sca::config::LspSession;
+use sca::{
+    build_report, collect_files, load_config, reasons, AnalysisEvent, Analyzer, AppConfig,
+    FullNodeInfo, ProjectAnalysis, Severity,
+};
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing_subscriber::EnvFilter;
 
 #[derive(Debug, Parser)]
 #[command(name = "sca")]
 #[command(about = "Smells Code Analyzer (Rust edition)")]
 struct Cli {
-    /// Path to JSON configuration file
-    #[arg(short = 'c', long = "config-file")]
-    config_file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to JSON configuration file, required except for `daemon`
+    #[arg(short = 'c', long = "config-file", global = true)]
+    config_file: Option<PathBuf>,
 
     /// Override threshold value from configuration
-    #[arg(short = 't', long = "threshold")]
+    #[arg(short = 't', long = "threshold", global = true)]
     threshold: Option<usize>,
 
     /// Path to a file containing newline separated file paths to analyse
-    #[arg(long = "files-from", value_name = "PATH")]
+    #[arg(long = "files-from", value_name = "PATH", global = true)]
     files_from: Option<PathBuf>,
 
     /// Generate snapshot of errors to JSON file
-    #[arg(long = "generate-snapshot", value_name = "PATH")]
+    #[arg(long = "generate-snapshot", value_name = "PATH", global = true)]
     generate_snapshot: Option<PathBuf>,
 
+    /// Include reference locations of every entity in the generated snapshot
+    #[arg(
+        long = "snapshot-locations",
+        requires = "generate_snapshot",
+        global = true
+    )]
+    snapshot_locations: bool,
+
     /// Compare with previous snapshot and show only new errors
-    #[arg(long = "compare-snapshot", value_name = "PATH")]
+    #[arg(long = "compare-snapshot", value_name = "PATH", global = true)]
     compare_snapshot: Option<PathBuf>,
+
+    /// Record every JSON-RPC message exchanged with the language server to a file
+    #[arg(
+        long = "lsp-record",
+        value_name = "PATH",
+        conflicts_with = "lsp_replay",
+        global = true
+    )]
+    lsp_record: Option<PathBuf>,
+
+    /// Replay a recorded LSP session instead of spawning the language server
+    #[arg(long = "lsp-replay", value_name = "PATH", global = true)]
+    lsp_replay: Option<PathBuf>,
+
+    /// Unix socket of the analysis daemon (defaults to one in `$XDG_RUNTIME_DIR`)
+    #[arg(long = "daemon-socket", value_name = "PATH", global = true)]
+    daemon_socket: Option<PathBuf>,
+
+    /// Analyse in this process even when a daemon is running
+    #[arg(long = "no-daemon", global = true)]
+    no_daemon: bool,
+
+    /// Seconds of daemon silence after which to analyse in process
+    #[arg(
+        long = "daemon-timeout",
+        value_name = "SECONDS",
+        default_value_t = 60,
+        global = true
+    )]
+    daemon_timeout: u64,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Remove dead entities from the analysed files
+    Fix {
+        /// Print the changes as a unified diff instead of writing the files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Write the changes as a patch to this file instead of modifying the sources
+        #[arg(long = "emit-patch", value_name = "PATH", conflicts_with = "dry_run")]
+        emit_patch: Option<PathBuf>,
+
+        /// Also rename entities with a useless prefix through the language server
+        #[arg(long = "rename-prefixes")]
+        rename_prefixes: bool,
+    },
+
+    /// Serve diagnostics and removal code actions to editors over stdio
+    Lsp,
+
+    /// Keep the language server running and re-analyse files as they change
+    Watch,
+
+    /// Keep analyzers warm for the runs of any configuration, on a Unix socket
+    Daemon,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_tracing();
     let cli = Cli::parse();
+    // Stdout carries the protocol when serving editors.
+    init_tracing(matches!(cli.command, Some(Command::Lsp)));
     let start_time = Instant::now();
+    let socket = cli
+        .daemon_socket
+        .clone()
+        .unwrap_or_else(daemon::default_socket);
+    if let Some(Command::Daemon) = cli.command {
+        return daemon::serve(&socket).await;
+    }
 
-    let config = load_config(&cli.config_file, cli.threshold)
-        .with_context(|| format!("Failed to load config {:?}", cli.config_file))?;
+    let config_file = cli
+        .config_file
+        .clone()
+        .context("--config-file is required")?;
+    let mut config = load_config(&config_file, cli.threshold)
+        .with_context(|| format!("Failed to load config {:?}", config_file))?;
+    if let Some(path) = &cli.lsp_record {
+        config.lsp_session = LspSession::Record(path.clone());
+    } else if let Some(path) = &cli.lsp_replay {
+        config.lsp_session = LspSession::Replay(path.clone());
+    }
 
-    let only_files = if let Some(files_list) = cli.files_from {
+    let only_files = if let Some(files_list) = &cli.files_from {
         Some(
-            load_target_file_set(&files_list)
+            load_target_file_set(files_list)
                 .with_context(|| format!("Failed to read file list {}", files_list.display()))?,
         )
     } else {
@@ -59,76 +148,242 @@ async fn main() -> Result<()> {
 
     tracing::info!("Using configuration {}", config.summary());
 
-    let mut analyzer = Analyzer::new(&config).await?;
-    let files = analyzer::files::collect_files(&config, only_files.as_ref())?;
+    if cli.command.is_none() && !cli.no_daemon && config.lsp_session == LspSession::Live {
+        let request = DaemonRequest::new(&config_file, only_files.as_ref())?;
+        if let Some(analysis) =
+            daemon::delegate(&socket, &request, Duration::from_secs(cli.daemon_timeout)).await
+        {
+            println!("FILES TO ANALYZE: {}", analysis.files);
+            print_reports(&analysis.nodes, config.show_passed);
+            let rules = RuleSet::from_config(&config)?;
+            let summaries = print_analysis(&rules, &analysis);
+            return finish(&cli, &config, &analysis.nodes, &summaries, start_time);
+        }
+    }
 
+    let mut analyzer = Analyzer::new(&config).await?;
+    if let Some(Command::Lsp) = cli.command {
+        return server::serve(&config, analyzer).await;
+    }
+    let files = collect_files(&config, only_files.as_ref())?;
     println!("FILES TO ANALYZE: {}", files.len());
-
-    let mut all_nodes = Vec::new();
-
-    for (index, path) in files.iter().enumerate() {
-        if config.show_progress {
-            println!("Analyze [{}/{}] {}", index + 1, files.len(), path.display());
+    let analysis = analyzer
+        .analyze_project(&files, |event| match event {
+            AnalysisEvent::Analyzing { index, total, path } => {
+                if config.show_progress {
+                    println!("Analyze [{index}/{total}] {}", path.display());
+                }
+            }
+            AnalysisEvent::Analyzed(nodes) => print_report(nodes, config.show_passed),
+        })
+        .await?;
+    let summaries = print_analysis(analyzer.rules(), &analysis);
+
+    match cli.command {
+        Some(Command::Watch) => {
+            let result = watch::watch(&config, &mut analyzer, analysis.nodes).await;
+            analyzer.shutdown().await?;
+            return result;
         }
-        let nodes = analyzer
-            .analyze_file(path)
-            .await
-            .with_context(|| format!("Failed to analyze {}", path.display()))?;
-
-        if !nodes.is_empty() {
-            let report = build_report(&nodes, config.show_passed);
-            if !report.is_empty() {
-                println!("{report}");
+        Some(Command::Fix {
+            dry_run,
+            ref emit_patch,
+            rename_prefixes,
+        }) => {
+            let mut plan = FixPlan::new(&config)?;
+            plan.remove_dead_code(&analysis.nodes)?;
+            if rename_prefixes {
+                for skipped in plan.rename_prefixes(&mut analyzer, &analysis.nodes).await? {
+                    println!("{skipped}");
+                }
             }
+            let fixes = plan.into_fixes();
+            analyzer.shutdown().await?;
+            if let Some(patch_path) = emit_patch {
+                return emit_fixes_patch(&config, &fixes, patch_path);
+            }
+            return apply_fixes(&config, &fixes, dry_run);
         }
-        all_nodes.extend(nodes);
+        Some(Command::Lsp) | Some(Command::Daemon) | None => {}
     }
 
-    let dead_count = count_dead_entities(&all_nodes);
-    println!("Found {} dead entities", dead_count);
+    analyzer.shutdown().await?;
+    finish(&cli, &config, &analysis.nodes, &summaries, start_time)
+}
 
-    if let Some(snapshot_path) = cli.generate_snapshot {
-        generate_snapshot(&all_nodes, &snapshot_path)?;
+/// Prints the unused suppressions and the rule summary once the reports are out.
+fn print_analysis(rules: &RuleSet, analysis: &ProjectAnalysis) -> Vec<RuleSummary> {
+    for unused in &analysis.unused_suppressions {
+        println!(
+            "Unused suppression {}:{} :: {}",
+            unused.file_path.display(),
+            unused.row,
+            unused.directive
+        );
+    }
+    print_summary(rules, &analysis.nodes)
+}
+
+/// Handles snapshots and thresholds once the language server is no longer needed.
+fn finish(
+    cli: &Cli,
+    config: &AppConfig,
+    nodes: &[FullNodeInfo],
+    summaries: &[RuleSummary],
+    start_time: Instant,
+) -> Result<()> {
+    if let Some(snapshot_path) = &cli.generate_snapshot {
+        generate_snapshot(nodes, snapshot_path, cli.snapshot_locations)?;
         println!("Snapshot saved to {}", snapshot_path.display());
     }
 
-    if let Some(snapshot_path) = cli.compare_snapshot {
-        let new_errors = compare_with_snapshot(&all_nodes, &snapshot_path)?;
+    if let Some(snapshot_path) = &cli.compare_snapshot {
+        let new_errors = compare_with_snapshot(nodes, snapshot_path)?;
         if !new_errors.is_empty() {
             println!("\nNew errors found:");
             for error in &new_errors {
                 println!("{}", format_error(error));
             }
-            analyzer.shutdown().await?;
-            anyhow::bail!("Found {} new errors", new_errors.len());
+            bail!("Found {} new errors", new_errors.len());
         } else {
             println!("No new errors found");
         }
     }
 
-    if let Some(threshold) = config.threshold {
-        if dead_count > threshold {
-            analyzer.shutdown().await?;
-            anyhow::bail!(
-                "Found {} dead entities, threshold is {}",
-                dead_count,
+    check_thresholds(summaries, config.threshold)?;
+
+    let elapsed = start_time.elapsed().as_secs_f64();
+    println!("Analyze took {elapsed:.3} s");
+    Ok(())
+}
+
+fn print_reports(nodes: &[FullNodeInfo], show_passed: bool) {
+    for nodes in nodes.chunk_by(|a, b| a.file_path == b.file_path) {
+        print_report(nodes, show_passed);
+    }
+}
+
+/// Prints the report of one file's entities.
+fn print_report(nodes: &[FullNodeInfo], show_passed: bool) {
+    let report = build_report(nodes, show_passed);
+    if !report.is_empty() {
+        println!("{report}");
+    }
+}
+
+fn print_summary(rules: &RuleSet, nodes: &[FullNodeInfo]) -> Vec<RuleSummary> {
+    println!("Found {} dead entities", count_findings(nodes, DEAD_CODE));
+    let summaries = rules.summarize(nodes);
+    for summary in summaries.iter().filter(|summary| summary.id != DEAD_CODE) {
+        println!(
+            "Found {} {} entities [{}]",
+            summary.count,
+            summary.id,
+            summary.severity.as_str()
+        );
+    }
+    summaries
+}
+
+fn apply_fixes(config: &AppConfig, fixes: &[FileFix], dry_run: bool) -> Result<()> {
+    let removed: usize = fixes.iter().map(|fix| fix.removed).sum();
+    let renamed: usize = fixes.iter().map(|fix| fix.renamed).sum();
+    if dry_run {
+        print!("{}", render_patch(fixes, &config.project_root_path)?);
+        println!(
+            "Would remove {} dead entities and rename {} entities in {} files",
+            removed,
+            renamed,
+            fixes.len()
+        );
+        return Ok(());
+    }
+
+    for fix in fixes {
+        fix.write(config)?;
+        println!(
+            "Fixed {} ({} removed, {} renamed)",
+            fix.path.display(),
+            fix.removed,
+            fix.renamed
+        );
+    }
+    println!(
+        "Removed {} dead entities and renamed {} entities in {} files",
+        removed,
+        renamed,
+        fixes.len()
+    );
+    Ok(())
+}
+
+fn emit_fixes_patch(config: &AppConfig, fixes: &[FileFix], path: &Path) -> Result<()> {
+    fs::write(path, render_patch(fixes, &config.project_root_path)?)
+        .with_context(|| format!("Failed to write patch {}", path.display()))?;
+    let removed: usize = fixes.iter().map(|fix| fix.removed).sum();
+    let renamed: usize = fixes.iter().map(|fix| fix.renamed).sum();
+    println!(
+        "Patch with {} removals and {} renames in {} files saved to {}",
+        removed,
+        renamed,
+        fixes.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Fails when error findings exceed the global threshold or a rule exceeds its own one.
+fn check_thresholds(summaries: &[RuleSummary], threshold: Option<usize>) -> Result<()> {
+    if let Some(threshold) = threshold {
+        let errors: Vec<_> = summaries
+            .iter()
+            .filter(|summary| summary.severity == Severity::Error && summary.count > 0)
+            .collect();
+        let error_count: usize = errors.iter().map(|summary| summary.count).sum();
+        if error_count > threshold {
+            if let [only] = errors.as_slice() {
+                if only.id == DEAD_CODE {
+                    bail!(
+                        "Found {} dead entities, threshold is {}",
+                        error_count,
+                        threshold
+                    );
+                }
+            }
+            let breakdown = errors
+                .iter()
+                .map(|summary| format!("{} {}", summary.count, summary.id))
+                .collect::<Vec<_>>()
+                .join(", ");
+            bail!(
+                "Found {} error entities ({}), threshold is {}",
+                error_count,
+                breakdown,
                 threshold
             );
         }
     }
 
-    analyzer.shutdown().await?;
-    let elapsed = start_time.elapsed().as_secs_f64();
-    println!("Analyze took {elapsed:.3} s");
+    if let Some(summary) = summaries.iter().find(|summary| summary.exceeds_threshold()) {
+        bail!(
+            "Rule {} found {} entities, threshold is {}",
+            summary.id,
+            summary.count,
+            summary.threshold.unwrap_or_default()
+        );
+    }
     Ok(())
 }
 
-fn init_tracing() {
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .try_init();
+fn init_tracing(to_stderr: bool) {
+    let builder = tracing_subscriber::fmt().with_env_filter(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    );
+    let _ = if to_stderr {
+        builder.with_writer(std::io::stderr).try_init()
+    } else {
+        builder.try_init()
+    };
 }
 
 fn load_target_file_set(path: &Path) -> Result<HashSet<PathBuf>> {
@@ -164,21 +419,21 @@ fn load_target_file_set(path: &Path) -> Result<HashSet<PathBuf>> {
     Ok(targets)
 }
 
-fn generate_snapshot(nodes: &[model::FullNodeInfo], path: &Path) -> Result<()> {
-    let errors: Vec<_> = nodes
-        .iter()
-        .flat_map(|node| collect_errors(node))
-        .collect();
+fn generate_snapshot(nodes: &[FullNodeInfo], path: &Path, include_locations: bool) -> Result<()> {
+    let mut errors: Vec<_> = nodes.iter().flat_map(collect_errors).collect();
+    if !include_locations {
+        errors.iter_mut().for_each(strip_locations);
+    }
 
     let json = serde_json::to_string_pretty(&errors)?;
     fs::write(path, json)?;
     Ok(())
 }
 
-fn collect_errors(node: &model::FullNodeInfo) -> Vec<model::FullNodeInfo> {
+fn collect_errors(node: &FullNodeInfo) -> Vec<FullNodeInfo> {
     let mut errors = Vec::new();
 
-    if node.references == 0 || node.parent_name_prefix {
+    if !reasons(node).is_empty() {
         errors.push(node.clone());
     }
 
@@ -189,20 +444,22 @@ fn collect_errors(node: &model::FullNodeInfo) -> Vec<model::FullNodeInfo> {
     errors
 }
 
+fn strip_locations(node: &mut FullNodeInfo) {
+    node.reference_locations.clear();
+    node.children.iter_mut().for_each(strip_locations);
+}
+
 fn compare_with_snapshot(
-    nodes: &[model::FullNodeInfo],
+    nodes: &[FullNodeInfo],
     snapshot_path: &Path,
-) -> Result<Vec<model::FullNodeInfo>> {
+) -> Result<Vec<FullNodeInfo>> {
     let snapshot_content = fs::read_to_string(snapshot_path)
         .with_context(|| format!("Failed to read snapshot {}", snapshot_path.display()))?;
 
-    let old_errors: Vec<model::FullNodeInfo> = serde_json::from_str(&snapshot_content)
+    let old_errors: Vec<FullNodeInfo> = serde_json::from_str(&snapshot_content)
         .with_context(|| format!("Failed to parse snapshot {}", snapshot_path.display()))?;
 
-    let current_errors: Vec<_> = nodes
-        .iter()
-        .flat_map(|node| collect_errors(node))
-        .collect();
+    let current_errors: Vec<_> = nodes.iter().flat_map(collect_errors).collect();
 
     let mut new_errors = Vec::new();
 
@@ -222,15 +479,8 @@ fn compare_with_snapshot(
     Ok(new_errors)
 }
 
-fn format_error(error: &model::FullNodeInfo) -> String {
-    let mut reasons = Vec::new();
-    if error.references == 0 {
-        reasons.push("dead code");
-    }
-    if error.parent_name_prefix {
-        reasons.push("useless prefix");
-    }
-    let reason_str = reasons.join(", ");
+fn format_error(error: &FullNodeInfo) -> String {
+    let reason_str = reasons(error).join(", ");
 
     format!(
         "{}:{}:{} :: {} ({})",
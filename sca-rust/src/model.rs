@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use std::path::PathBuf;
 use tree_sitter::Point;
 
+/// Syntax node type to report, from `referenceNodes`.
 #[derive(Debug, Clone)]
 pub struct NodeTarget {
     pub node_type: String,
@@ -9,27 +11,125 @@ pub struct NodeTarget {
     pub children: Vec<NodeTarget>,
 }
 
+/// Tree-sitter kinds of function parameters and destructured parameter fields.
+pub const PARAMETER_KINDS: [&str; 5] = [
+    "required_parameter",
+    "optional_parameter",
+    "shorthand_property_identifier_pattern",
+    "pair_pattern",
+    "object_assignment_pattern",
+];
+
+/// How much a finding matters; only errors count against `threshold`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+}
+
+/// Span of a whole syntax node, from its first to its last character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceRange {
+    pub start: Point,
+    pub end: Point,
+}
+
+impl SourceRange {
+    pub fn contains(&self, point: Point) -> bool {
+        (self.start.row, self.start.column) <= (point.row, point.column)
+            && (point.row, point.column) <= (self.end.row, self.end.column)
+    }
+}
+
+/// Structural size of a matched node, used by the size rules.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeMetrics {
+    pub properties: usize,
+    pub methods: usize,
+    pub lines: usize,
+    /// Deepest chain of nested statement blocks inside the node.
+    pub depth: usize,
+}
+
+/// Entity found in a syntax tree, before references are known.
 #[derive(Debug, Clone)]
 pub struct NodeInfo {
     pub node_type: String,
     pub name: String,
     pub start_position: Point,
+    /// Span of the declaration, starting at its `export` keyword or first decorator.
+    pub range: SourceRange,
+    /// Declared inside an `export` statement.
+    pub exported: bool,
+    /// Bytes to delete when removing the node: its export statement, the comments right
+    /// above it and the blank line after it.
+    pub removal: Range<usize>,
+    pub metrics: NodeMetrics,
     pub children: Vec<NodeInfo>,
 }
 
+/// Place where an entity is used, as reported by the language server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferenceLocation {
+    pub file_path: PathBuf,
+    #[serde(with = "point_serde")]
+    pub start: Point,
+    #[serde(with = "point_serde")]
+    pub end: Point,
+}
+
+/// Analysed entity with its references and findings.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FullNodeInfo {
-    #[allow(dead_code)]
     pub node_type: String,
     pub name: String,
     #[serde(with = "point_serde")]
     pub start_position: Point,
     pub file_path: PathBuf,
+    #[serde(default)]
+    pub exported: bool,
     pub references: usize,
-    pub parent_name_prefix: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reference_locations: Vec<ReferenceLocation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    #[serde(skip)]
+    pub metrics: NodeMetrics,
+    /// Span in the sanitized source, where usages from the same file are reported.
+    #[serde(skip)]
+    pub range: SourceRange,
+    /// Span in the file on disk, where usages from other files are reported.
+    #[serde(skip)]
+    pub raw_range: SourceRange,
     pub children: Vec<FullNodeInfo>,
 }
 
+impl FullNodeInfo {
+    pub fn is_parameter(&self) -> bool {
+        PARAMETER_KINDS.contains(&self.node_type.as_str())
+    }
+}
+
+/// Smell reported by a rule on a single entity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
 mod point_serde {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use tree_sitter::Point;
@@ -11,14 +11,40 @@ lazy_static! {
 }
 
 pub fn sanitize_source(input: &str) -> String {
-    let without_line = LINE_COMMENTS.replace_all(input, "");
-    let without_block = BLOCK_COMMENTS.replace_all(&without_line, "");
-    let without_console = CONSOLE_LOGS.replace_all(&without_block, "");
-    let ascii_only: String = without_console
-        .chars()
-        .map(|c| if c.is_ascii() { c } else { ' ' })
-        .collect();
-    CYRILLIC.replace_all(&ascii_only, "").into_owned()
+    sanitize_with_offsets(input).0
+}
+
+/// Sanitizes like [`sanitize_source`], also returning the offset in `input` of every byte
+/// of the result followed by `input.len()`, to map sanitized positions back to the file.
+pub fn sanitize_with_offsets(input: &str) -> (String, Vec<usize>) {
+    let mut text = input.to_string();
+    let mut offsets: Vec<usize> = (0..=input.len()).collect();
+    for pattern in [&*LINE_COMMENTS, &*BLOCK_COMMENTS, &*CONSOLE_LOGS] {
+        (text, offsets) = remove_matches(pattern, &text, &offsets);
+    }
+
+    let mut ascii_only = String::with_capacity(text.len());
+    let mut ascii_offsets = Vec::with_capacity(offsets.len());
+    for (index, c) in text.char_indices() {
+        ascii_only.push(if c.is_ascii() { c } else { ' ' });
+        ascii_offsets.push(offsets[index]);
+    }
+    ascii_offsets.push(offsets[text.len()]);
+    remove_matches(&CYRILLIC, &ascii_only, &ascii_offsets)
+}
+
+fn remove_matches(pattern: &Regex, text: &str, offsets: &[usize]) -> (String, Vec<usize>) {
+    let mut kept = String::with_capacity(text.len());
+    let mut kept_offsets = Vec::with_capacity(offsets.len());
+    let mut start = 0;
+    for found in pattern.find_iter(text) {
+        kept.push_str(&text[start..found.start()]);
+        kept_offsets.extend_from_slice(&offsets[start..found.start()]);
+        start = found.end();
+    }
+    kept.push_str(&text[start..]);
+    kept_offsets.extend_from_slice(&offsets[start..]);
+    (kept, kept_offsets)
 }
 
 #[cfg(test)]
@@ -41,4 +67,15 @@ mod tests {
         assert!(!sanitized.contains("console.log"));
         assert!(!sanitized.contains("текст"));
     }
+
+    #[test]
+    fn maps_sanitized_offsets_back_to_the_input() {
+        let source = "/* note */ const имя = 1; // tail\nuser();\n";
+        let (sanitized, offsets) = sanitize_with_offsets(source);
+        assert_eq!(sanitized, " const     = 1; \nuser();\n");
+        assert_eq!(offsets.len(), sanitized.len() + 1);
+        let user = sanitized.find("user").unwrap();
+        assert_eq!(&source[offsets[user]..], "user();\n");
+        assert_eq!(offsets[sanitized.len()], source.len());
+    }
 }
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, InitializeResult,
+    PublishDiagnosticsParams, Range, SaveOptions, ServerCapabilities, ServerInfo,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url, WorkspaceEdit,
+};
+use sca::analyzer::diagnostics::{DiagnosticBuilder, EntityDiagnostic};
+use sca::analyzer::files::collect_files;
+use sca::analyzer::Analyzer;
+use sca::analyzer::{FramedTransport, Transport};
+use sca::config::AppConfig;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+const METHOD_NOT_FOUND: i32 = -32601;
+const INTERNAL_ERROR: i32 = -32603;
+
+/// Editor buffer and the diagnostics of its last analysis.
+struct Document {
+    text: String,
+    diagnostics: Vec<EntityDiagnostic>,
+    /// Edited since the last analysis, so removal ranges no longer match the text.
+    stale: bool,
+}
+
+/// Language server publishing analysis results to editors, over stdin and stdout.
+pub async fn serve(config: &AppConfig, analyzer: Analyzer) -> Result<()> {
+    let transport = FramedTransport::new(tokio::io::stdin(), tokio::io::stdout());
+    let mut server = LanguageServer {
+        config,
+        analyzer,
+        diagnostics: DiagnosticBuilder::new(config)?,
+        transport: Box::new(transport),
+        documents: HashMap::new(),
+    };
+    let result = server.run().await;
+    server.analyzer.shutdown().await?;
+    result
+}
+
+struct LanguageServer<'c> {
+    config: &'c AppConfig,
+    analyzer: Analyzer,
+    diagnostics: DiagnosticBuilder,
+    transport: Box<dyn Transport>,
+    documents: HashMap<Url, Document>,
+}
+
+impl LanguageServer<'_> {
+    async fn run(&mut self) -> Result<()> {
+        loop {
+            let message = self
+                .transport
+                .receive()
+                .await
+                .context("Editor closed the stream without exit")?;
+            let method = message.get("method").and_then(Value::as_str);
+            let params = message.get("params").cloned().unwrap_or(Value::Null);
+            match (method, message.get("id")) {
+                (Some("exit"), _) => return Ok(()),
+                (Some(method), Some(id)) => {
+                    let (code, message) = match self.handle_request(method, params).await {
+                        Ok(Some(result)) => {
+                            let response = json!({"jsonrpc": "2.0", "id": id, "result": result});
+                            self.transport.send(&response).await?;
+                            continue;
+                        }
+                        Ok(None) => (METHOD_NOT_FOUND, format!("Unsupported method {method}")),
+                        Err(err) => (INTERNAL_ERROR, format!("{err:#}")),
+                    };
+                    let response = json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message }
+                    });
+                    self.transport.send(&response).await?;
+                }
+                (Some(method), None) => {
+                    let method = method.to_string();
+                    if let Err(err) = self.handle_notification(&method, params).await {
+                        tracing::warn!("Failed to handle {method}: {err:#}");
+                    }
+                }
+                // Responses to requests this server never sends.
+                (None, _) => {}
+            }
+        }
+    }
+
+    async fn handle_request(&mut self, method: &str, params: Value) -> Result<Option<Value>> {
+        let result = match method {
+            "initialize" => serde_json::to_value(initialize_result())?,
+            "shutdown" => Value::Null,
+            "textDocument/codeAction" => {
+                let params: CodeActionParams = parse_params(params)?;
+                serde_json::to_value(self.code_actions(&params))?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    async fn handle_notification(&mut self, method: &str, params: Value) -> Result<()> {
+        match method {
+            "textDocument/didOpen" => {
+                let params: DidOpenTextDocumentParams = parse_params(params)?;
+                let document = params.text_document;
+                self.analyze(document.uri, document.text).await
+            }
+            "textDocument/didSave" => {
+                let params: DidSaveTextDocumentParams = parse_params(params)?;
+                let uri = params.text_document.uri;
+                let text = match params.text {
+                    Some(text) => Some(text),
+                    None => self
+                        .documents
+                        .get(&uri)
+                        .map(|document| document.text.clone()),
+                };
+                match text {
+                    Some(text) => self.analyze(uri, text).await,
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didChange" => {
+                let params: DidChangeTextDocumentParams = parse_params(params)?;
+                if let Some(document) = self.documents.get_mut(&params.text_document.uri) {
+                    // Full sync: the last change holds the whole text.
+                    if let Some(change) = params.content_changes.into_iter().last() {
+                        document.text = change.text;
+                    }
+                    document.stale = true;
+                }
+                Ok(())
+            }
+            "textDocument/didClose" => {
+                let params: DidCloseTextDocumentParams = parse_params(params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish(params.text_document.uri, &[]).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Analyses a document of the configured project and publishes its diagnostics.
+    async fn analyze(&mut self, uri: Url, text: String) -> Result<()> {
+        let Some(path) = self.analyzed_path(&uri) else {
+            tracing::debug!("Skip document outside the analysed files: {uri}");
+            return Ok(());
+        };
+        let diagnostics = match self.analyzer.analyze_source(&path, &text).await {
+            Ok(nodes) => self.diagnostics.build(&text, &nodes)?,
+            Err(err) => {
+                tracing::warn!("Failed to analyze {}: {err:#}", path.display());
+                Vec::new()
+            }
+        };
+        self.publish(uri.clone(), &diagnostics).await?;
+        self.documents.insert(
+            uri,
+            Document {
+                text,
+                diagnostics,
+                stale: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Applies the file globs and content pattern of the configuration to `uri`.
+    fn analyzed_path(&self, uri: &Url) -> Option<PathBuf> {
+        let path = uri.to_file_path().ok()?;
+        let only = HashSet::from([path.clone()]);
+        collect_files(self.config, Some(&only))
+            .ok()
+            .filter(|files| !files.is_empty())
+            .map(|_| path)
+    }
+
+    async fn publish(&mut self, uri: Url, diagnostics: &[EntityDiagnostic]) -> Result<()> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics: diagnostics
+                .iter()
+                .map(|entity| entity.diagnostic.clone())
+                .collect(),
+            version: None,
+        };
+        self.transport
+            .send(&json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": params,
+            }))
+            .await
+    }
+
+    fn code_actions(&self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        let Some(document) = self.documents.get(uri).filter(|document| !document.stale) else {
+            return Vec::new();
+        };
+        document
+            .diagnostics
+            .iter()
+            .filter(|entity| overlaps(entity.diagnostic.range, params.range))
+            .filter_map(|entity| {
+                let removal = entity.removal.clone()?;
+                Some(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Remove dead {}", entity.name),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![entity.diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![removal])])),
+                        ..Default::default()
+                    }),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }))
+            })
+            .collect()
+    }
+}
+
+fn initialize_result() -> InitializeResult {
+    InitializeResult {
+        capabilities: ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Options(
+                TextDocumentSyncOptions {
+                    open_close: Some(true),
+                    change: Some(TextDocumentSyncKind::FULL),
+                    save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                        include_text: Some(true),
+                    })),
+                    ..Default::default()
+                },
+            )),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            ..Default::default()
+        },
+        server_info: Some(ServerInfo {
+            name: "sca".to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }),
+    }
+}
+
+fn overlaps(a: Range, b: Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T> {
+    serde_json::from_value(params).context("Invalid request parameters")
+}
@@ -0,0 +1,157 @@
+use crate::{print_reports, print_summary};
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sca::analyzer::files::collect_files;
+use sca::analyzer::Analyzer;
+use sca::config::AppConfig;
+use sca::model::FullNodeInfo;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Editors save through several file system events; they are handled as one change.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Re-analyses changed files with the running analyzer until interrupted.
+pub async fn watch(
+    config: &AppConfig,
+    analyzer: &mut Analyzer,
+    nodes: Vec<FullNodeInfo>,
+) -> Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                let _ = sender.send(event.paths);
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("File watcher error: {err}"),
+        })?;
+    watcher
+        .watch(&config.analyze_directory, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", config.analyze_directory.display()))?;
+    println!(
+        "Watching {} for changes",
+        config.analyze_directory.display()
+    );
+
+    let mut state = WatchState {
+        config,
+        analyzer,
+        nodes,
+    };
+    // Created once, so an interrupt during an update is not lost.
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let paths = tokio::select! {
+            paths = receiver.recv() => paths,
+            _ = &mut interrupted => None,
+        };
+        let Some(paths) = paths else {
+            return Ok(());
+        };
+        let mut changed: BTreeSet<PathBuf> = paths.into_iter().collect();
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(paths) = receiver.try_recv() {
+            changed.extend(paths);
+        }
+        state.update(changed).await?;
+    }
+}
+
+struct WatchState<'a> {
+    config: &'a AppConfig,
+    analyzer: &'a mut Analyzer,
+    /// Nodes of every analysed file, grouped by file.
+    nodes: Vec<FullNodeInfo>,
+}
+
+impl WatchState<'_> {
+    async fn update(&mut self, changed: BTreeSet<PathBuf>) -> Result<()> {
+        let changed: BTreeSet<_> = changed
+            .into_iter()
+            .filter(|path| self.matches_globs(path))
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut targets = changed.clone();
+        targets.extend(
+            self.nodes
+                .iter()
+                .filter(|node| affected_by(node, &changed))
+                .map(|node| node.file_path.clone()),
+        );
+        self.nodes.retain(|node| !targets.contains(&node.file_path));
+
+        for path in &targets {
+            if !self.is_analyzed(path) {
+                self.analyzer.forget_file(path);
+                continue;
+            }
+            match self.analyzer.analyze_file(path).await {
+                Ok(nodes) => self.nodes.extend(nodes),
+                Err(err) => println!("Failed to analyze {}: {err:#}", path.display()),
+            }
+        }
+        // Stable, so the node order inside each file is kept.
+        self.nodes.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        self.analyzer.apply_project_rules(&mut self.nodes);
+
+        println!(
+            "Changed {}, re-analyzed {} files",
+            changed
+                .iter()
+                .map(|path| self.display(path))
+                .collect::<Vec<_>>()
+                .join(", "),
+            targets.len()
+        );
+        let updated: Vec<_> = self
+            .nodes
+            .iter()
+            .filter(|node| targets.contains(&node.file_path))
+            .cloned()
+            .collect();
+        print_reports(&updated, self.config.show_passed);
+        print_summary(self.analyzer.rules(), &self.nodes);
+        Ok(())
+    }
+
+    /// Deleted files must still match so their nodes are dropped.
+    fn matches_globs(&self, path: &Path) -> bool {
+        path.starts_with(&self.config.analyze_directory)
+            && self.config.file_matching_glob.is_match(path)
+            && !self.config.file_exclude_glob.is_match(path)
+    }
+
+    fn is_analyzed(&self, path: &Path) -> bool {
+        path.is_file()
+            && collect_files(self.config, Some(&HashSet::from([path.to_path_buf()])))
+                .is_ok_and(|files| !files.is_empty())
+    }
+
+    fn display(&self, path: &Path) -> String {
+        path.strip_prefix(&self.config.project_root_path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+/// Entities used from a changed file may have lost usages.
+///
+/// Usages a changed file gains are not known before analysing it again, so dead entities
+/// elsewhere keep their findings until their own file changes.
+fn affected_by(node: &FullNodeInfo, changed: &BTreeSet<PathBuf>) -> bool {
+    node.reference_locations
+        .iter()
+        .any(|location| changed.contains(&location.file_path))
+        || node
+            .children
+            .iter()
+            .any(|child| affected_by(child, changed))
+}
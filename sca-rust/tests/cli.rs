@@ -0,0 +1,1101 @@
+use assert_cmd::Command;
+use serde_json::json;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+const MODELS_TS: &str = r#"export interface User {
+  name: string;
+  age: number;
+}
+
+export class UserService {
+  userServiceCache: string;
+}
+"#;
+
+struct Project {
+    dir: TempDir,
+}
+
+impl Project {
+    fn new(fixture: serde_json::Value) -> Self {
+        Self::with_config(fixture, json!({}))
+    }
+
+    /// Builds the project with `overrides` merged into the generated `sca.json`.
+    fn with_config(fixture: serde_json::Value, overrides: serde_json::Value) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/models.ts"), MODELS_TS).unwrap();
+        fs::write(
+            dir.path().join("fixture.json"),
+            serde_json::to_vec_pretty(&fixture).unwrap(),
+        )
+        .unwrap();
+
+        let mut config = json!({
+            "lspExecutable": env!("CARGO_BIN_EXE_sca-fake-lsp"),
+            "lspArgs": ["--fixture", dir.path().join("fixture.json")],
+            "lspName": "typescript",
+            "grammar": "typescript",
+            "projectRootPath": ".",
+            "analyzeDirectory": "src",
+            "fileMatchingRegexp": "**/*.ts",
+            "referenceNodes": [
+                {
+                    "type": "interface_declaration",
+                    "refType": "type_identifier",
+                    "children": [{ "type": "property_signature", "refType": "property_identifier" }]
+                },
+                {
+                    "type": "class_declaration",
+                    "refType": "type_identifier",
+                    "children": [{ "type": "public_field_definition", "refType": "property_identifier" }]
+                }
+            ]
+        });
+        if let (Some(config), Some(overrides)) = (config.as_object_mut(), overrides.as_object()) {
+            config.extend(overrides.clone());
+        }
+        fs::write(
+            dir.path().join("sca.json"),
+            serde_json::to_vec_pretty(&config).unwrap(),
+        )
+        .unwrap();
+
+        Self { dir }
+    }
+
+    fn root(&self) -> PathBuf {
+        fs::canonicalize(self.dir.path()).unwrap()
+    }
+
+    fn path(&self, relative: &str) -> PathBuf {
+        self.root().join(relative)
+    }
+
+    /// Runs `sca` in process, so a daemon of the user running the tests is never used.
+    fn sca(&self) -> Command {
+        let mut cmd = self.sca_with_daemon();
+        cmd.arg("--no-daemon");
+        cmd
+    }
+
+    fn sca_with_daemon(&self) -> Command {
+        let mut cmd = Command::cargo_bin("sca").unwrap();
+        cmd.env("RUST_LOG", "off")
+            .arg("--config-file")
+            .arg(self.path("sca.json"));
+        cmd
+    }
+
+    fn normalize(&self, output: &[u8]) -> String {
+        let text = String::from_utf8_lossy(output);
+        text.replace(&self.root().display().to_string(), "[ROOT]")
+            .lines()
+            .filter(|line| !line.starts_with("Analyze took"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn default_fixture() -> serde_json::Value {
+    json!({
+        "references": {
+            "User": 2,
+            "name": 1,
+            "userServiceCache": 1
+        }
+    })
+}
+
+#[test]
+fn reports_dead_code_and_prefixes() {
+    let project = Project::new(default_fixture());
+    let output = project.sca().assert().success().get_output().clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+}
+
+#[test]
+fn fails_when_threshold_is_exceeded() {
+    let project = Project::new(default_fixture());
+    let output = project
+        .sca()
+        .args(["--threshold", "1"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Found 2 dead entities, threshold is 1")
+    );
+
+    project.sca().args(["--threshold", "2"]).assert().success();
+}
+
+#[test]
+fn snapshot_comparison_reports_only_new_errors() {
+    let project = Project::new(default_fixture());
+    let snapshot = project.path("errors.json");
+
+    project
+        .sca()
+        .arg("--generate-snapshot")
+        .arg(&snapshot)
+        .assert()
+        .success();
+    project
+        .sca()
+        .arg("--compare-snapshot")
+        .arg(&snapshot)
+        .assert()
+        .success();
+
+    fs::write(
+        project.path("fixture.json"),
+        serde_json::to_vec(&json!({ "references": { "User": 2 } })).unwrap(),
+    )
+    .unwrap();
+    let output = project
+        .sca()
+        .arg("--compare-snapshot")
+        .arg(&snapshot)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+}
+
+#[test]
+fn replays_recorded_session_without_server() {
+    let project = Project::new(default_fixture());
+    let session = project.path("session.jsonl");
+
+    let recorded = project
+        .sca()
+        .arg("--lsp-record")
+        .arg(&session)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    fs::remove_file(project.path("fixture.json")).unwrap();
+    let replayed = project
+        .sca()
+        .arg("--lsp-replay")
+        .arg(&session)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    assert_eq!(
+        project.normalize(&recorded.stdout),
+        project.normalize(&replayed.stdout)
+    );
+}
+
+#[test]
+fn connects_to_shared_server_over_tcp() {
+    let fixture_dir = tempfile::tempdir().unwrap();
+    let fixture = fixture_dir.path().join("fixture.json");
+    fs::write(&fixture, serde_json::to_vec(&default_fixture()).unwrap()).unwrap();
+
+    let mut server = std::process::Command::new(env!("CARGO_BIN_EXE_sca-fake-lsp"))
+        .arg("--fixture")
+        .arg(&fixture)
+        .args(["--tcp", "127.0.0.1:0"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut address = String::new();
+    BufReader::new(server.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+
+    let project = Project::with_config(
+        default_fixture(),
+        json!({ "lspExecutable": "", "lspTransport": { "tcp": address.trim() } }),
+    );
+    let stdio = Project::new(default_fixture());
+    let expected = stdio.normalize(&stdio.sca().assert().success().get_output().stdout);
+
+    // The server stays up between runs, so the second run reuses it.
+    for _ in 0..2 {
+        let output = project.sca().assert().success().get_output().clone();
+        assert_eq!(project.normalize(&output.stdout), expected);
+    }
+
+    server.kill().unwrap();
+    let _ = server.wait();
+}
+
+/// An LSIF dump of `MODELS_TS` giving each declaration the usages of `default_fixture`,
+/// as produced on a machine with the project checked out in `/ci/app`.
+fn lsif_dump() -> String {
+    let declarations = [
+        ("User", 0, 17, 21, 2),
+        ("name", 1, 2, 6, 1),
+        ("age", 2, 2, 5, 0),
+        ("UserService", 5, 13, 24, 0),
+        ("userServiceCache", 6, 2, 18, 1),
+    ];
+    let mut elements = vec![
+        json!({ "id": 1, "type": "vertex", "label": "metaData", "projectRoot": "file:///ci/app" }),
+        json!({ "id": 2, "type": "vertex", "label": "document", "uri": "file:///ci/app/src/models.ts" }),
+        json!({ "id": 3, "type": "vertex", "label": "document", "uri": "file:///ci/app/src/usages.ts" }),
+    ];
+    let mut id = 3;
+    let mut vertex = |elements: &mut Vec<serde_json::Value>, mut element: serde_json::Value| {
+        id += 1;
+        element["id"] = json!(id);
+        elements.push(element);
+        id
+    };
+    let range = |line: u32, start: u32, end: u32| {
+        json!({
+            "type": "vertex", "label": "range",
+            "start": { "line": line, "character": start },
+            "end": { "line": line, "character": end }
+        })
+    };
+    for (usage_line, (_, line, start, end, usages)) in declarations.into_iter().enumerate() {
+        let declaration = vertex(&mut elements, range(line, start, end));
+        let result = vertex(
+            &mut elements,
+            json!({ "type": "vertex", "label": "referenceResult" }),
+        );
+        let edge = json!({ "type": "edge", "label": "textDocument/references", "outV": declaration, "inV": result });
+        vertex(&mut elements, edge);
+        let edge = json!({ "type": "edge", "label": "item", "outV": result, "inVs": [declaration], "document": 2 });
+        vertex(&mut elements, edge);
+        for usage in 0..usages {
+            let usage = vertex(
+                &mut elements,
+                range(usage_line as u32, usage * 20, usage * 20 + 4),
+            );
+            let edge = json!({ "type": "edge", "label": "item", "outV": result, "inVs": [usage], "document": 3 });
+            vertex(&mut elements, edge);
+        }
+    }
+    elements
+        .iter()
+        .map(|element| element.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn reads_references_from_an_index_without_server() {
+    let project = Project::with_config(
+        default_fixture(),
+        json!({ "lspExecutable": "", "referenceProvider": { "index": "dump.lsif" } }),
+    );
+    fs::write(project.path("dump.lsif"), lsif_dump()).unwrap();
+    fs::remove_file(project.path("fixture.json")).unwrap();
+    let output = project.sca().assert().success().get_output().clone();
+
+    let server = Project::new(default_fixture());
+    let expected = server.normalize(&server.sca().assert().success().get_output().stdout);
+    assert_eq!(project.normalize(&output.stdout), expected);
+}
+
+#[test]
+fn ignores_references_from_excluded_files() {
+    let fixture = json!({
+        "references": {
+            "User": 2,
+            "name": [{ "file": "src/models.spec.ts", "line": 3, "character": 8 }],
+            "userServiceCache": 1
+        }
+    });
+    let overrides = json!({ "referenceFilters": { "excludeGlobs": ["**/*.spec.ts"] } });
+
+    let plain = Project::new(fixture.clone());
+    let output = plain.sca().assert().success().get_output().clone();
+    assert!(!plain.normalize(&output.stdout).contains("name:1:2"));
+
+    let filtered = Project::with_config(fixture, overrides);
+    let output = filtered.sca().assert().success().get_output().clone();
+    assert!(filtered
+        .normalize(&output.stdout)
+        .contains("[💩] name:1:2 :: (dead code)"));
+}
+
+#[test]
+fn reports_entities_used_only_in_tests() {
+    let fixture = json!({
+        "references": {
+            "User": [
+                { "file": "src/models.spec.ts", "line": 1, "character": 4 },
+                { "file": "src/main.ts", "line": 2, "character": 4 }
+            ],
+            "name": [{ "file": "src/models.spec.ts", "line": 3, "character": 8 }],
+            "age": [{ "file": "src/models.test.ts", "line": 3, "character": 8 }],
+            "userServiceCache": 1
+        }
+    });
+    let project = Project::with_config(
+        fixture,
+        json!({ "testFileGlobs": ["**/*.spec.ts", "**/*.test.ts"] }),
+    );
+
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout.contains("[✅] User:0:17 :: ()"));
+    assert!(stdout.contains("[💩] name:1:2 :: (used only in tests [warning])"));
+    assert!(stdout.contains("[💩] age:2:2 :: (used only in tests [warning])"));
+}
+
+#[test]
+fn low_usage_errors_count_against_threshold() {
+    let warning = Project::with_config(
+        default_fixture(),
+        json!({ "lowUsage": { "maxReferences": 1 }, "threshold": 3 }),
+    );
+    let output = warning.sca().assert().success().get_output().clone();
+    let stdout = warning.normalize(&output.stdout);
+    assert!(stdout.contains("[💩] name:1:2 :: (low usage [warning])"));
+    assert!(stdout.contains("Found 2 low-usage entities [warning]"));
+
+    let error = Project::with_config(
+        default_fixture(),
+        json!({
+            "lowUsage": { "maxReferences": 1, "severity": "error", "nodeTypes": ["property_signature"] },
+            "threshold": 2
+        }),
+    );
+    let output = error.sca().assert().failure().get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Found 3 error entities (2 dead-code, 1 low-usage), threshold is 2"));
+}
+
+#[test]
+fn applies_rule_severities_and_thresholds() {
+    let project = Project::with_config(
+        default_fixture(),
+        json!({
+            "threshold": 0,
+            "rules": {
+                "dead-code": { "severity": "warning" },
+                "useless-prefix": { "threshold": 0 }
+            }
+        }),
+    );
+    let output = project.sca().assert().failure().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout.contains("[💩] age:2:2 :: (dead code [warning])"));
+    assert!(stdout.contains("Found 1 useless-prefix entities [warning]"));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Rule useless-prefix found 1 entities, threshold is 0"));
+
+    let disabled = Project::with_config(
+        default_fixture(),
+        json!({ "rules": { "useless-prefix": { "enabled": false } } }),
+    );
+    let output = disabled.sca().assert().success().get_output().clone();
+    assert!(!disabled
+        .normalize(&output.stdout)
+        .contains("useless prefix"));
+
+    let unknown = Project::with_config(default_fixture(), json!({ "rules": { "typo": {} } }));
+    let output = unknown.sca().assert().failure().get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown rule 'typo'"));
+
+    let missing = Project::with_config(
+        default_fixture(),
+        json!({ "rules": { "unreachable": { "enabled": true } } }),
+    );
+    let output = missing.sca().assert().failure().get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "Rule 'unreachable' is enabled in rules configuration but needs the `reachability` section"
+    ));
+}
+
+#[test]
+fn honours_suppression_comments() {
+    let project = Project::new(default_fixture());
+    fs::write(
+        project.path("src/models.ts"),
+        MODELS_TS
+            .replace("  age", "  // sca-ignore-next-line dead-code\n  age")
+            .replace(
+                "export class",
+                "// sca-ignore-next-line useless-prefix\nexport class",
+            ),
+    )
+    .unwrap();
+
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(!stdout.contains("age:"));
+    assert!(stdout.contains("[💩] UserService:7:13 :: (dead code)"));
+    assert!(stdout.contains(
+        "Unused suppression [ROOT]/src/models.ts:6 :: sca-ignore-next-line useless-prefix"
+    ));
+    assert!(stdout.contains("Found 1 dead entities"));
+
+    fs::write(
+        project.path("src/models.ts"),
+        format!("/* sca-ignore-file */\n{MODELS_TS}"),
+    )
+    .unwrap();
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout.contains("Found 0 dead entities"));
+    assert!(!stdout.contains("Unused suppression"));
+}
+
+#[test]
+fn suppresses_decorated_declarations() {
+    let project = Project::new(default_fixture());
+    fs::write(
+        project.path("src/widget.ts"),
+        "// sca-ignore-next-line dead-code\n@Component({\n  selector: \"app-widget\",\n})\nexport class Widget {}\n\n// sca-ignore-next-line dead-code\n@Injectable()\nclass Store {}\n",
+    )
+    .unwrap();
+
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(!stdout.contains("Widget:"), "{stdout}");
+    assert!(!stdout.contains("Store:"), "{stdout}");
+    assert!(!stdout.contains("Unused suppression"), "{stdout}");
+}
+
+#[test]
+fn reports_oversized_entities() {
+    let project = Project::with_config(
+        default_fixture(),
+        json!({ "size": { "maxProperties": 1, "maxLines": 3, "nodeTypes": ["interface_declaration"] } }),
+    );
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout
+        .contains("[💩] User:0:17 :: (2 properties (max 1) [warning], 4 lines (max 3) [warning])"));
+    assert!(stdout.contains("Found 1 too-many-properties entities [warning]"));
+    assert!(!stdout.contains("too-many-methods"));
+}
+
+#[test]
+fn reports_names_declared_in_several_files() {
+    let project = Project::with_config(
+        default_fixture(),
+        json!({ "duplicates": { "nodeTypes": ["interface_declaration"] } }),
+    );
+    fs::write(
+        project.path("src/other.ts"),
+        "export interface User {}\ninterface UserService {}\n",
+    )
+    .unwrap();
+
+    let output = project.sca().assert().success().get_output().clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout.contains("[💩] User:0:17 :: (name also declared in src/other.ts [warning])"));
+    assert!(stdout.contains("[💩] User:0:17 :: (name also declared in src/models.ts [warning])"));
+    assert!(!stdout.contains("UserService:1:10 :: (name also"));
+    assert!(stdout.contains("Found 2 duplicate-name entities [warning]"));
+}
+
+#[test]
+fn streams_reports_unless_project_rules_are_enabled() {
+    let position = |stdout: &str, text: &str| stdout.find(text).unwrap();
+    for (overrides, streamed) in [
+        (json!({ "showProgress": true }), true),
+        (
+            json!({ "showProgress": true, "duplicates": { "nodeTypes": ["interface_declaration"] } }),
+            false,
+        ),
+    ] {
+        let project = Project::with_config(default_fixture(), overrides);
+        fs::write(
+            project.path("src/cache.ts"),
+            "export interface Cache {}
+",
+        )
+        .unwrap();
+
+        let output = project.sca().assert().success().get_output().clone();
+        let stdout = project.normalize(&output.stdout);
+        let report = position(&stdout, "[💩] Cache:0:17");
+        let next_file = position(&stdout, "Analyze [2/2]");
+        assert_eq!(report < next_file, streamed, "{stdout}");
+    }
+}
+
+#[test]
+fn reports_unused_parameters() {
+    let project = Project::with_config(
+        json!({ "references": { "User": 2, "name": 1, "userServiceCache": 1, "render": 1, "id": 1, "size": 1 } }),
+        json!({
+            "parameters": { "ignoreUnderscore": true },
+            "referenceNodes": [{ "type": "function_declaration", "refType": "identifier" }]
+        }),
+    );
+    fs::write(
+        project.path("src/render.ts"),
+        "export function render(id: string, { title, size }: Props, _ctx: Ctx) {\n  return id + size;\n}\n",
+    )
+    .unwrap();
+
+    let output = project.sca().assert().success().get_output().clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+}
+
+#[test]
+fn reports_entities_unreachable_from_entry_points() {
+    // `UserService` and `Legacy` only use each other; `User` is used by the entry point.
+    let project = Project::with_config(
+        json!({
+            "references": {
+                "User": [{ "file": "src/main.ts", "line": 1, "character": 4 }],
+                "name": [{ "file": "src/main.ts", "line": 2, "character": 4 }],
+                "UserService": [{ "file": "src/legacy.ts", "line": 1, "character": 11 }],
+                "userServiceCache": [{ "file": "src/legacy.ts", "line": 2, "character": 4 }],
+                "Legacy": [{ "file": "src/models.ts", "line": 6, "character": 20 }]
+            }
+        }),
+        json!({ "reachability": { "entryPoints": ["src/main.ts"] } }),
+    );
+    fs::write(
+        project.path("src/legacy.ts"),
+        "export class Legacy {\n  service: UserService;\n}\n",
+    )
+    .unwrap();
+
+    let output = project.sca().assert().success().get_output().clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+
+    // Without `src/legacy.ts`, its usage of `UserService` may come from a reached entity.
+    fs::write(project.path("changed-files.txt"), "src/models.ts\n").unwrap();
+    let output = project
+        .sca()
+        .arg("--files-from")
+        .arg(project.path("changed-files.txt"))
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = project.normalize(&output.stdout);
+    assert!(stdout.contains("FILES TO ANALYZE: 1"), "{stdout}");
+    assert!(stdout.contains("Found 0 unreachable entities"), "{stdout}");
+}
+
+#[test]
+fn fix_removes_dead_entities() {
+    let project = Project::new(default_fixture());
+
+    let output = project
+        .sca()
+        .args(["fix", "--dry-run"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        MODELS_TS
+    );
+
+    // Shared flags may also follow the subcommand.
+    let reordered = Command::cargo_bin("sca")
+        .unwrap()
+        .env("RUST_LOG", "off")
+        .args(["fix", "--dry-run", "-c"])
+        .arg(project.path("sca.json"))
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(
+        project.normalize(&reordered.stdout),
+        project.normalize(&output.stdout)
+    );
+
+    project.sca().arg("fix").assert().success();
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        "export interface User {\n  name: string;\n}\n"
+    );
+}
+
+#[test]
+fn fix_renames_useless_prefixes() {
+    let project = Project::new(json!({
+        "references": {
+            "User": 2,
+            "name": 1,
+            "age": 1,
+            "UserService": 1,
+            "userServiceCache": 1,
+            "CacheStore": 1,
+            "cacheStoreItems": 1,
+            "items": 1
+        }
+    }));
+    fs::write(project.path("usages.ts"), "userServiceCache;\n").unwrap();
+    fs::write(
+        project.path("src/cache.ts"),
+        "export class CacheStore {\n  cacheStoreItems: string;\n  items: number;\n}\n",
+    )
+    .unwrap();
+
+    let output = project
+        .sca()
+        .args(["fix", "--dry-run", "--rename-prefixes"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    insta::assert_snapshot!(project.normalize(&output.stdout));
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        MODELS_TS
+    );
+
+    project
+        .sca()
+        .args(["fix", "--rename-prefixes"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        MODELS_TS.replace("userServiceCache", "cache")
+    );
+    assert_eq!(
+        fs::read_to_string(project.path("usages.ts")).unwrap(),
+        "cache;\n"
+    );
+}
+
+#[test]
+fn fix_refuses_renames_colliding_with_other_members() {
+    const STORE_TS: &str = "export class SessionStore {\n  sessionStoreCache: string;\n  cache(): string {\n    return this.sessionStoreCache;\n  }\n}\n";
+    let project = Project::new(json!({
+        "references": {
+            "User": 2,
+            "name": 1,
+            "age": 1,
+            "UserService": 1,
+            "userServiceCache": 1,
+            "SessionStore": 1,
+            "sessionStoreCache": 1
+        }
+    }));
+    fs::write(project.path("usages.ts"), "userServiceCache;\n").unwrap();
+    fs::write(project.path("src/store.ts"), STORE_TS).unwrap();
+
+    // `cache` is a method, which the configured reference nodes do not cover.
+    let output = project
+        .sca()
+        .args(["fix", "--rename-prefixes"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(project.normalize(&output.stdout).contains(
+        "Refusing to rename sessionStoreCache to cache in [ROOT]/src/store.ts: cache already exists"
+    ));
+    assert_eq!(
+        fs::read_to_string(project.path("src/store.ts")).unwrap(),
+        STORE_TS
+    );
+}
+
+#[test]
+fn fix_emits_patch_applicable_with_git() {
+    let project = Project::new(default_fixture());
+    let patch = project.path("out.diff");
+
+    let output = project
+        .sca()
+        .args(["fix", "--emit-patch"])
+        .arg(&patch)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(project
+        .normalize(&output.stdout)
+        .contains("Patch with 2 removals and 0 renames in 1 files saved to [ROOT]/out.diff"));
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        MODELS_TS
+    );
+    insta::assert_snapshot!(fs::read_to_string(&patch).unwrap());
+
+    let status = std::process::Command::new("git")
+        .arg("apply")
+        .arg(&patch)
+        .current_dir(project.root())
+        .status()
+        .expect("git is available");
+    assert!(status.success());
+    assert_eq!(
+        fs::read_to_string(project.path("src/models.ts")).unwrap(),
+        "export interface User {\n  name: string;\n}\n"
+    );
+}
+
+fn write_frame(writer: &mut impl std::io::Write, message: serde_json::Value) {
+    let body = serde_json::to_vec(&message).unwrap();
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len()).unwrap();
+    writer.write_all(&body).unwrap();
+    writer.flush().unwrap();
+}
+
+fn read_frame(reader: &mut impl BufRead) -> serde_json::Value {
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(value) = line.trim().strip_prefix("Content-Length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    std::io::Read::read_exact(reader, &mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn serves_diagnostics_and_removal_actions_to_editors() {
+    let project = Project::new(json!({
+        "references": { "User": 2, "name": 1, "UserService": 1, "userServiceCache": 1 }
+    }));
+    let mut server = std::process::Command::new(env!("CARGO_BIN_EXE_sca"))
+        .env("RUST_LOG", "off")
+        .arg("--config-file")
+        .arg(project.path("sca.json"))
+        .arg("lsp")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let uri = url::Url::from_file_path(project.path("src/models.ts")).unwrap();
+
+    write_frame(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+    );
+    let initialized = read_frame(&mut stdout);
+    assert_eq!(
+        initialized["result"]["capabilities"]["codeActionProvider"],
+        true
+    );
+    write_frame(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+    );
+
+    write_frame(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": uri, "languageId": "typescript", "version": 1, "text": MODELS_TS }
+        }}),
+    );
+    let published = read_frame(&mut stdout);
+    assert_eq!(published["method"], "textDocument/publishDiagnostics");
+    insta::assert_snapshot!(project.normalize(
+        serde_json::to_string_pretty(&published["params"]["diagnostics"])
+            .unwrap()
+            .as_bytes()
+    ));
+
+    write_frame(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/codeAction", "params": {
+            "textDocument": { "uri": uri },
+            "range": { "start": { "line": 2, "character": 4 }, "end": { "line": 2, "character": 4 } },
+            "context": { "diagnostics": [] }
+        }}),
+    );
+    let actions = read_frame(&mut stdout);
+    let action = &actions["result"][0];
+    assert_eq!(action["title"], "Remove dead age");
+    assert_eq!(
+        action["edit"]["changes"][uri.as_str()][0],
+        json!({
+            "range": { "start": { "line": 2, "character": 0 }, "end": { "line": 3, "character": 0 } },
+            "newText": ""
+        })
+    );
+
+    write_frame(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+    );
+    assert_eq!(read_frame(&mut stdout)["id"], 3);
+    write_frame(&mut stdin, json!({"jsonrpc": "2.0", "method": "exit"}));
+    assert!(server.wait().unwrap().success());
+}
+
+/// Streams the stdout lines of a long-running child through a channel.
+fn stdout_lines(child: &mut std::process::Child) -> std::sync::mpsc::Receiver<String> {
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, lines) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    lines
+}
+
+/// Collects stdout lines until one starts with `prefix`, failing after a few seconds.
+fn read_until(lines: &std::sync::mpsc::Receiver<String>, prefix: &str) -> Vec<String> {
+    let mut seen = Vec::new();
+    loop {
+        let line = lines
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .unwrap_or_else(|_| panic!("no line starting with {prefix:?} in {seen:#?}"));
+        let done = line.starts_with(prefix);
+        seen.push(line);
+        if done {
+            return seen;
+        }
+    }
+}
+
+#[test]
+fn watch_reanalyzes_changed_files() {
+    let project = Project::new(default_fixture());
+    fs::write(
+        project.path("src/notes.txt"),
+        "not matched by fileMatchingRegexp\n",
+    )
+    .unwrap();
+    let mut watcher = std::process::Command::new(env!("CARGO_BIN_EXE_sca"))
+        .env("RUST_LOG", "off")
+        .arg("--config-file")
+        .arg(project.path("sca.json"))
+        .arg("watch")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = stdout_lines(&mut watcher);
+
+    let initial = read_until(&lines, "Watching");
+    assert!(initial.contains(&"Found 2 dead entities".to_string()));
+
+    fs::write(project.path("src/notes.txt"), "still ignored\n").unwrap();
+    fs::write(
+        project.path("src/models.ts"),
+        MODELS_TS.replace("  age: number;\n", ""),
+    )
+    .unwrap();
+    let update = read_until(&lines, "Found 1 dead entities");
+    let changed = update
+        .iter()
+        .find(|line| line.starts_with("Changed"))
+        .unwrap();
+    assert_eq!(changed, "Changed src/models.ts, re-analyzed 1 files");
+
+    watcher.kill().unwrap();
+    let _ = watcher.wait();
+}
+
+#[test]
+fn watch_reanalyzes_files_used_by_changed_files() {
+    let mut fixture = default_fixture();
+    fixture["references"]["CacheStore"] =
+        json!([{ "file": "src/models.ts", "line": 6, "character": 2 }]);
+    let project = Project::new(fixture);
+    fs::write(project.path("src/cache.ts"), "export class CacheStore {}\n").unwrap();
+    let mut watcher = std::process::Command::new(env!("CARGO_BIN_EXE_sca"))
+        .env("RUST_LOG", "off")
+        .arg("--config-file")
+        .arg(project.path("sca.json"))
+        .arg("watch")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = stdout_lines(&mut watcher);
+    read_until(&lines, "Watching");
+
+    // `CacheStore` may have lost its only usage, so `src/cache.ts` is analysed again.
+    fs::write(
+        project.path("src/models.ts"),
+        MODELS_TS.replace("  age: number;\n", ""),
+    )
+    .unwrap();
+    let update = read_until(&lines, "Changed");
+    assert_eq!(
+        update.last().unwrap(),
+        "Changed src/models.ts, re-analyzed 2 files"
+    );
+
+    watcher.kill().unwrap();
+    let _ = watcher.wait();
+}
+
+#[test]
+fn watch_skips_unrelated_files_with_dead_code() {
+    let project = Project::new(default_fixture());
+    fs::write(project.path("src/orphan.ts"), "export class Orphan {}\n").unwrap();
+    let mut watcher = std::process::Command::new(env!("CARGO_BIN_EXE_sca"))
+        .env("RUST_LOG", "off")
+        .arg("--config-file")
+        .arg(project.path("sca.json"))
+        .arg("watch")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = stdout_lines(&mut watcher);
+    let initial = read_until(&lines, "Watching");
+    assert!(initial.contains(&"Found 3 dead entities".to_string()));
+
+    // `Orphan` is not used from `src/models.ts`, so its file keeps its findings.
+    fs::write(
+        project.path("src/models.ts"),
+        MODELS_TS.replace("  age: number;\n", ""),
+    )
+    .unwrap();
+    let update = read_until(&lines, "Found 2 dead entities");
+    let changed = update
+        .iter()
+        .find(|line| line.starts_with("Changed"))
+        .unwrap();
+    assert_eq!(changed, "Changed src/models.ts, re-analyzed 1 files");
+
+    watcher.kill().unwrap();
+    let _ = watcher.wait();
+}
+
+#[test]
+fn delegates_runs_to_daemon() {
+    let project = Project::new(default_fixture());
+    let socket = project.path("sca.sock");
+    let mut daemon = std::process::Command::new(env!("CARGO_BIN_EXE_sca"))
+        .env("RUST_LOG", "off")
+        .arg("--daemon-socket")
+        .arg(&socket)
+        .arg("daemon")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = stdout_lines(&mut daemon);
+    read_until(&lines, "Listening on");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let local = project
+        .sca()
+        .arg("--generate-snapshot")
+        .arg(project.path("local.json"))
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    // A client that never sends its request does not hold up the others.
+    #[cfg(unix)]
+    let _idle = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    // The second run reuses the analyzer started by the first one.
+    for _ in 0..2 {
+        let delegated = project
+            .sca_with_daemon()
+            .arg("--daemon-socket")
+            .arg(&socket)
+            .arg("--generate-snapshot")
+            .arg(project.path("delegated.json"))
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        read_until(&lines, "Analyzed 1 files for");
+        assert_eq!(
+            project
+                .normalize(&delegated.stdout)
+                .replace("delegated.json", "local.json"),
+            project.normalize(&local.stdout)
+        );
+        assert_eq!(
+            fs::read_to_string(project.path("delegated.json")).unwrap(),
+            fs::read_to_string(project.path("local.json")).unwrap()
+        );
+    }
+
+    daemon.kill().unwrap();
+    let _ = daemon.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn falls_back_when_daemon_does_not_answer() {
+    let project = Project::new(default_fixture());
+    let socket = project.path("sca.sock");
+    // Accepts connections but never answers them.
+    let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+    let local = project.sca().assert().success().get_output().clone();
+    let delegated = project
+        .sca_with_daemon()
+        .arg("--daemon-socket")
+        .arg(&socket)
+        .args(["--daemon-timeout", "1"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(
+        project.normalize(&delegated.stdout),
+        project.normalize(&local.stdout)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn waits_for_daemon_sending_heartbeats() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let project = Project::new(default_fixture());
+    let socket = project.path("sca.sock");
+    let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    // Busy for longer than the client timeout, but answering every half second.
+    let daemon = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let hello = format!(
+            r#"{{"hello":{{"version":"{}","busy":true}}}}"#,
+            env!("CARGO_PKG_VERSION")
+        );
+        writeln!(stream, "{hello}").unwrap();
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request).unwrap();
+        for _ in 0..5 {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            writeln!(stream, r#""working""#).unwrap();
+        }
+        writeln!(
+            stream,
+            r#"{{"analysis":{{"files":7,"nodes":[],"unusedSuppressions":[]}}}}"#
+        )
+        .unwrap();
+    });
+
+    let output = project
+        .sca_with_daemon()
+        .arg("--daemon-socket")
+        .arg(&socket)
+        .args(["--daemon-timeout", "1"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    daemon.join().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FILES TO ANALYZE: 7"));
+}
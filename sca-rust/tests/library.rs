@@ -0,0 +1,136 @@
+use anyhow::Result;
+use sca::lsp_types::{Location, Position, Range};
+use sca::tree_sitter::Point;
+use sca::url::Url;
+use sca::{
+    collect_files, load_config, Analyzer, AppConfig, BoxFuture, FullNodeInfo, ReferenceProvider,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// A project with `src/models.ts`, like the one of the CLI tests.
+struct Project {
+    _dir: TempDir,
+    root: PathBuf,
+}
+
+impl Project {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(
+            root.join("src/models.ts"),
+            "export interface User {\n  name: string;\n  age: number;\n}\n",
+        )
+        .unwrap();
+        Self { _dir: dir, root }
+    }
+
+    fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// Writes `sca.json` with `overrides` merged into the defaults and loads it.
+    fn config(&self, overrides: serde_json::Value) -> AppConfig {
+        let mut config = json!({
+            "lspName": "typescript",
+            "grammar": "typescript",
+            "projectRootPath": ".",
+            "analyzeDirectory": "src",
+            "fileMatchingRegexp": "**/*.ts",
+            "referenceNodes": [{
+                "type": "interface_declaration",
+                "refType": "type_identifier",
+                "children": [{ "type": "property_signature", "refType": "property_identifier" }]
+            }]
+        });
+        if let (Some(config), Some(overrides)) = (config.as_object_mut(), overrides.as_object()) {
+            config.extend(overrides.clone());
+        }
+        fs::write(self.path("sca.json"), config.to_string()).unwrap();
+        load_config(&self.path("sca.json"), None).unwrap()
+    }
+}
+
+/// Names of the children of `node` reported as dead code.
+fn dead_children(node: &FullNodeInfo) -> Vec<&str> {
+    node.children
+        .iter()
+        .filter(|child| {
+            child
+                .findings
+                .iter()
+                .any(|finding| finding.rule == "dead-code")
+        })
+        .map(|child| child.name.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn embeds_the_analyzer() {
+    let project = Project::new();
+    fs::write(
+        project.path("fixture.json"),
+        json!({ "references": { "User": 1, "name": 1 } }).to_string(),
+    )
+    .unwrap();
+    let config = project.config(json!({
+        "lspExecutable": env!("CARGO_BIN_EXE_sca-fake-lsp"),
+        "lspArgs": ["--fixture", project.path("fixture.json")],
+    }));
+    let mut analyzer = Analyzer::new(&config).await.unwrap();
+    let files = collect_files(&config, None).unwrap();
+    let analysis = analyzer.analyze_project(&files, |_| {}).await.unwrap();
+    analyzer.shutdown().await.unwrap();
+
+    let user = &analysis.nodes[0];
+    assert_eq!(user.name, "User");
+    assert!(user.findings.is_empty());
+    assert_eq!(dead_children(user), ["age"]);
+}
+
+/// Reports the declaration plus a fixed number of usages per name.
+struct CountingProvider {
+    usages: HashMap<&'static str, u32>,
+}
+
+impl ReferenceProvider for CountingProvider {
+    fn references<'a>(
+        &'a mut self,
+        uri: &'a Url,
+        position: Point,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Location>>> {
+        let usages = self.usages.get(name).copied().unwrap_or_default();
+        let at = |line: u32, character: u32| Location {
+            uri: uri.clone(),
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            ),
+        };
+        let mut locations = vec![at(position.row as u32, position.column as u32)];
+        locations.extend((0..usages).map(|usage| at(100 + usage, 0)));
+        Box::pin(async move { Ok(locations) })
+    }
+}
+
+#[tokio::test]
+async fn analyzes_with_a_custom_reference_provider() {
+    let project = Project::new();
+    let config = project.config(json!({ "lspExecutable": "never-started" }));
+    let provider = CountingProvider {
+        usages: HashMap::from([("User", 2), ("age", 1)]),
+    };
+    let mut analyzer = Analyzer::with_provider(&config, Box::new(provider)).unwrap();
+    let files = collect_files(&config, None).unwrap();
+    let analysis = analyzer.analyze_project(&files, |_| {}).await.unwrap();
+
+    let user = &analysis.nodes[0];
+    assert_eq!(user.references, 2);
+    assert_eq!(dead_children(user), ["name"]);
+}